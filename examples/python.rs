@@ -30,7 +30,7 @@ async fn main() {
 
     let kernels = client.get_running_kernels().await.unwrap();
     let kernel = kernels.iter().find(|each| each.name == "python3").unwrap();
-    let kernsl_cli = client.new_kernel_client(&kernel).unwrap();
+    let kernsl_cli = client.new_kernel_client(kernel).unwrap();
 
     let resp = kernsl_cli.run_code("12 * 22".into(), None).await;
     println!("{resp:?}");
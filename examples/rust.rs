@@ -36,7 +36,7 @@ async fn main() {
     let kernels = client.get_running_kernels().await.unwrap();
 
     let kernel = kernels.iter().find(|each| each.name == "rust").unwrap();
-    let kernsl_cli = client.new_kernel_client(&kernel).unwrap();
+    let kernsl_cli = client.new_kernel_client(kernel).unwrap();
     let resp = kernsl_cli.run_code(":dep tokio".into(), None).await;
     println!("{resp:?}");
 
@@ -0,0 +1,268 @@
+use super::error::JupyterApiError;
+use super::kernel::{InputProvider, KernelClientConfig};
+use super::proxy::{ProxyConfig, ProxyMode};
+use super::retry::RetryPolicy;
+use super::tls::TlsConfig;
+use super::transport::{Transport, UnixRequestConfig};
+use super::{Credential, JupyterClient};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
+use reqwest::Client;
+use std::sync::Arc;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, JupyterApiError>;
+
+pub struct JupyterClientBuilder {
+    base_url: String,
+    credential: Option<Credential>,
+    req_client: Option<Client>,
+    tls: TlsConfig,
+    proxy: ProxyMode,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    kernel_timeout: Option<Duration>,
+    default_headers: HeaderMap,
+    user_agent: Option<String>,
+    username: Option<String>,
+    retry_policy: RetryPolicy,
+    input_provider: Option<Arc<dyn InputProvider>>,
+}
+
+impl JupyterClientBuilder {
+    /// `base_url` is `http://`, `https://` or `http+unix://` with the percent encoded socket path.
+    /// e.g. `http+unix://%2Frun%2Fuser%2F1000%2Fjupyter.sock`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            credential: None,
+            req_client: None,
+            tls: TlsConfig::default(),
+            proxy: ProxyMode::default(),
+            connect_timeout: None,
+            request_timeout: None,
+            kernel_timeout: None,
+            default_headers: HeaderMap::new(),
+            user_agent: None,
+            username: None,
+            retry_policy: RetryPolicy::default(),
+            input_provider: None,
+        }
+    }
+
+    pub fn credential(mut self, credential: Option<Credential>) -> Self {
+        self.credential = credential;
+        self
+    }
+
+    /// Use the http client as is for the REST api calls.
+    /// The tls config, the proxy, the timeouts and the headers are then applied only to the kernel websocket
+    /// and to the REST api calls over a unix socket, which do not go through the http client.
+    pub fn req_client(mut self, req_client: Option<Client>) -> Self {
+        self.req_client = req_client;
+        self
+    }
+
+    /// TLS settings for both the REST api calls and the kernel websocket.
+    pub fn tls_config(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Proxy for both the REST api calls and the kernel websocket.
+    /// Without this, the proxy is taken from `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = ProxyMode::Custom(proxy);
+        self
+    }
+
+    /// Connect directly ignoring the proxy environment variables.
+    pub fn no_proxy(mut self) -> Self {
+        self.proxy = ProxyMode::NoProxy;
+        self
+    }
+
+    /// Timeout to connect to the server, for both the REST api calls and the kernel websocket.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of each REST api call.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Default timeout to wait for the kernel replies, e.g. the end of an execution. 120 seconds by default.
+    pub fn kernel_timeout(mut self, timeout: Duration) -> Self {
+        self.kernel_timeout = Some(timeout);
+        self
+    }
+
+    /// Headers sent with every REST api call and the websocket handshake.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// `username` in the header of the kernel messages. `jupyter-client-rs` by default.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Answers the `input_request`s of the executions with `allow_stdin`.
+    pub fn input_provider<P: InputProvider + 'static>(mut self, provider: P) -> Self {
+        self.input_provider = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn build(self) -> Result<JupyterClient> {
+        let (base_url, transport) = Transport::from_base_url(&self.base_url)?;
+        let base_url = base_url.strip_suffix('/').unwrap_or(&base_url).to_string();
+
+        let req_client = match self.req_client {
+            Some(req_client) => req_client,
+            None => {
+                let mut builder = self
+                    .tls
+                    .apply_to_req_client_builder(Client::builder())?
+                    .default_headers(self.default_headers.clone());
+                match &self.proxy {
+                    ProxyMode::System => {}
+                    ProxyMode::Custom(proxy) => builder = builder.proxy(proxy.to_req_proxy()?),
+                    ProxyMode::NoProxy => builder = builder.no_proxy(),
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(timeout) = self.request_timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                builder.build()?
+            }
+        };
+
+        let mut headers = self.default_headers;
+        if let Some(user_agent) = &self.user_agent {
+            headers.insert(USER_AGENT, HeaderValue::from_str(user_agent)?);
+        }
+        let unix_config = UnixRequestConfig {
+            headers: headers.clone(),
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+        };
+
+        let mut ws_headers = headers;
+        if let Some(Credential::Token(token)) = &self.credential {
+            ws_headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("token {token}"))?,
+            );
+        }
+
+        Ok(JupyterClient {
+            base_url,
+            credential: self.credential,
+            req_client,
+            transport: transport.clone(),
+            unix_config,
+            retry_policy: self.retry_policy.clone(),
+            kernel_config: KernelClientConfig {
+                tls: self.tls,
+                proxy: self.proxy,
+                transport,
+                connect_timeout: self.connect_timeout,
+                kernel_timeout: self.kernel_timeout,
+                headers: ws_headers,
+                username: self.username,
+                retry_policy: self.retry_policy,
+                input_provider: self.input_provider,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::kernel::{KernelRequest, MessageType};
+    use super::super::types::Kernel;
+    use super::*;
+    use futures_util::StreamExt;
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[tokio::test]
+    // the error response of the handshake callback is defined by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn kernel_channel_settings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let kernel = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut handshake = None;
+            let mut socket =
+                tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+                    handshake = Some(request.headers().clone());
+                    Ok::<Response, _>(response)
+                })
+                .await
+                .unwrap();
+            let message = match socket.next().await {
+                Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text).unwrap(),
+                message => panic!("unexpected {message:?}"),
+            };
+            (handshake.unwrap(), message)
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-team", HeaderValue::from_static("data"));
+        let client = JupyterClient::builder(&format!("http://{addr}"))
+            .credential(Some(Credential::Token("secret".to_string())))
+            .default_headers(headers)
+            .user_agent("notebook-runner/1.0")
+            .username("alice")
+            .no_proxy()
+            .build()
+            .unwrap();
+        let connection = client
+            .new_kernel_client(&Kernel {
+                id: "kernel".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        let mut request = serde_json::to_value(KernelRequest::new(
+            MessageType::KernelInfoRequest,
+            json!({}),
+        ))
+        .unwrap();
+        request["parent_header"] = json!({"msg_id": "parent", "username": "bob"});
+        connection.send_message(&request).unwrap();
+
+        let (handshake, message) = kernel.await.unwrap();
+        assert_eq!(handshake["x-team"], "data");
+        assert_eq!(handshake[USER_AGENT], "notebook-runner/1.0");
+        assert_eq!(handshake[AUTHORIZATION], "token secret");
+        assert_eq!(message["header"]["username"], "alice");
+        assert_eq!(message["parent_header"]["username"], "bob");
+    }
+}
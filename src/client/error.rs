@@ -1,4 +1,6 @@
+use serde::{Deserialize, Serialize};
 use serde_json::error::Error as JsonError;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,27 +20,262 @@ pub enum JupyterApiError {
     #[error("json error: {0}")]
     JsonError(#[from] JsonError),
 
+    #[error("hyper error: {0}")]
+    HyperError(#[from] hyper::Error),
+
+    #[error("http error: {0}")]
+    HttpError(#[from] hyper::http::Error),
+
+    #[error("invalid header value: {0}")]
+    InvalidHeaderValue(#[from] reqwest::header::InvalidHeaderValue),
+
+    #[error("connect timeout")]
+    ConnectTimeout,
+
+    #[error("request timeout")]
+    RequestTimeout,
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("tls error: {0}")]
+    TlsError(#[from] native_tls::Error),
+
     #[error("ws error: {0}")]
-    WsError(#[from] tokio_tungstenite::tungstenite::Error),
+    WsError(Box<tokio_tungstenite::tungstenite::Error>),
 
-    #[error("bad request: {0}")]
-    BadRequest(String),
+    #[error("{0}")]
+    ApiError(Box<ApiErrorResponse>),
 
     #[error("kernel message timeout ")]
     KernelMessageTimeout,
 
-    #[error("timeout specified kernel response error")]
-    KernelResponseTimeout,
-
     #[error("kernel message error: {0}")]
     KernelMessageError(String),
 
     #[error("connection closed by kernel")]
     KernelConnectionClosed,
 
-    #[error("internal server error: {0}")]
-    InternalServerError(String),
-
     #[error("empty response")]
     EmptyResponse,
+
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("config parse error: {0}")]
+    ConfigParseError(#[from] toml::de::Error),
+
+    #[error("config error: {0}")]
+    ConfigError(String),
+
+    #[error("proxy error: {0}")]
+    ProxyError(String),
+
+    #[error("profile not found: {0}")]
+    ProfileNotFound(String),
+
+    #[error("the kernel requested input but no input provider is set (prompt: {0:?})")]
+    InputProviderNotSet(String),
+
+    #[error("input error: {0}")]
+    InputError(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for JupyterApiError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::WsError(Box::new(e))
+    }
+}
+
+impl JupyterApiError {
+    /// The error response of the REST api call, if this is the one.
+    pub fn api_error(&self) -> Option<&ApiErrorResponse> {
+        match self {
+            Self::ApiError(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Http status of the REST api error response.
+    pub fn status(&self) -> Option<u16> {
+        self.api_error().map(|e| e.status)
+    }
+
+    /// Transient errors worth retrying: connection failures, timeouts, and 502, 503 and 504 responses.
+    pub fn is_retryable(&self) -> bool {
+        use tokio_tungstenite::tungstenite::Error as WsError;
+        match self {
+            Self::ReqwestError(e) => e.is_connect() || e.is_timeout(),
+            Self::HyperError(e) => e.is_connect() || e.is_closed() || e.is_incomplete_message(),
+            Self::IoError(_) | Self::ConnectTimeout | Self::RequestTimeout => true,
+            Self::WsError(e) => match e.as_ref() {
+                WsError::Io(_) | WsError::ConnectionClosed | WsError::AlreadyClosed => true,
+                WsError::Http(response) => matches!(response.status().as_u16(), 502..=504),
+                _ => false,
+            },
+            Self::ApiError(e) => matches!(e.status, 502..=504),
+            _ => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.api_error()
+            .map(|e| e.kind() == ApiErrorKind::NotFound)
+            .unwrap_or(false)
+    }
+}
+
+/// Error response of the jupyter server REST api.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiErrorResponse {
+    pub status: u16,
+    pub method: String,
+    /// Path of the api. e.g. `/api/kernels/{kernel_id}`
+    pub endpoint: String,
+    /// The json error body returned by the jupyter server, if any.
+    pub body: Option<JupyterErrorBody>,
+    /// The raw response body.
+    pub text: String,
+}
+
+impl ApiErrorResponse {
+    pub fn kind(&self) -> ApiErrorKind {
+        ApiErrorKind::from_status(self.status)
+    }
+
+    /// `message` of the error body, or the raw response body.
+    pub fn message(&self) -> &str {
+        self.body
+            .as_ref()
+            .and_then(|body| body.message.as_deref())
+            .unwrap_or(&self.text)
+    }
+}
+
+impl fmt::Display for ApiErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} returned {}: {}",
+            self.method,
+            self.endpoint,
+            self.status,
+            self.message()
+        )
+    }
+}
+
+/// The json error body of the jupyter server. `{"message": .., "reason": .., "traceback": ..}`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JupyterErrorBody {
+    pub message: Option<String>,
+    pub reason: Option<String>,
+    pub traceback: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ApiErrorKind {
+    /// 400
+    BadRequest,
+    /// 401
+    Unauthorized,
+    /// 403
+    Forbidden,
+    /// 404
+    NotFound,
+    /// 409
+    Conflict,
+    /// 503
+    ServiceUnavailable,
+    /// Other 4xx
+    ClientError,
+    /// Other 5xx
+    ServerError,
+    Other,
+}
+
+impl ApiErrorKind {
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 => Self::BadRequest,
+            401 => Self::Unauthorized,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            409 => Self::Conflict,
+            503 => Self::ServiceUnavailable,
+            _ if (400..500).contains(&status) => Self::ClientError,
+            _ if (500..600).contains(&status) => Self::ServerError,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self, Self::Unauthorized | Self::Forbidden)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::check_status;
+    use super::*;
+    use reqwest::Method;
+
+    fn response(status: u16, body: &str) -> reqwest::Response {
+        reqwest::Response::from(
+            hyper::Response::builder()
+                .status(status)
+                .body(body.to_string())
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn api_error_kind() {
+        assert_eq!(ApiErrorKind::from_status(404), ApiErrorKind::NotFound);
+        assert_eq!(ApiErrorKind::from_status(418), ApiErrorKind::ClientError);
+        assert_eq!(
+            ApiErrorKind::from_status(503),
+            ApiErrorKind::ServiceUnavailable
+        );
+        assert_eq!(ApiErrorKind::from_status(500), ApiErrorKind::ServerError);
+        assert_eq!(ApiErrorKind::from_status(302), ApiErrorKind::Other);
+        assert!(ApiErrorKind::from_status(403).is_auth_error());
+        assert!(!ApiErrorKind::from_status(404).is_auth_error());
+    }
+
+    #[tokio::test]
+    async fn check_error_response() {
+        let endpoint = "/api/kernels/missing";
+        let ok = check_status(response(200, "{}"), &Method::GET, endpoint).await;
+        assert!(ok.is_ok());
+
+        let body = r#"{"message": "Kernel does not exist: missing", "reason": null}"#;
+        let e = check_status(response(404, body), &Method::GET, endpoint)
+            .await
+            .unwrap_err();
+        assert!(e.is_not_found());
+        assert!(!e.is_retryable());
+        let api_error = e.api_error().unwrap();
+        assert_eq!(api_error.method, "GET");
+        assert_eq!(api_error.endpoint, endpoint);
+        assert_eq!(
+            api_error.body,
+            Some(JupyterErrorBody {
+                message: Some("Kernel does not exist: missing".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            e.to_string(),
+            "GET /api/kernels/missing returned 404: Kernel does not exist: missing"
+        );
+
+        let e = check_status(response(502, "Bad Gateway"), &Method::POST, endpoint)
+            .await
+            .unwrap_err();
+        assert_eq!(e.status(), Some(502));
+        assert_eq!(e.api_error().unwrap().body, None);
+        assert_eq!(e.api_error().unwrap().message(), "Bad Gateway");
+        assert!(e.is_retryable());
+    }
 }
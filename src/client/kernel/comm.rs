@@ -0,0 +1,214 @@
+use super::connection::{KernelConnection, WeakKernelConnection};
+use super::types::*;
+use super::Result;
+use crate::client::error::JupyterApiError;
+use bytes::Bytes;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::Stream;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use uuid::Uuid;
+
+/// Called with the comm opened by the kernel and the data of its `comm_open`.
+pub type CommTargetHandler = Arc<dyn Fn(Comm, CommMessage) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommMessage {
+    pub data: Value,
+    pub buffers: Vec<Bytes>,
+}
+
+impl CommMessage {
+    pub(crate) fn new(data: Value, resp: &KernelResponse) -> Self {
+        Self {
+            data,
+            buffers: resp.buffers.clone(),
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_value(self.data.clone())?)
+    }
+}
+
+/// A message from the kernel to a comm.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommEvent {
+    Msg(CommMessage),
+    /// The kernel closed the comm. No more events follow.
+    Close(CommMessage),
+}
+
+/// One end of a comm between the kernel and this client.
+///
+/// Yields the `comm_msg`s from the kernel until it closes the comm.
+/// A comm does not keep the connection open, so that the comms held by the target handlers
+/// do not keep it alive: it ends when the last [`KernelConnection`] is dropped.
+pub struct Comm {
+    comm_id: String,
+    target_name: String,
+    connection: WeakKernelConnection,
+    events: UnboundedReceiver<CommEvent>,
+}
+
+impl Comm {
+    pub(crate) fn attach(
+        connection: &KernelConnection,
+        comm_id: &str,
+        target_name: &str,
+    ) -> Result<Self> {
+        let events = connection.register_comm(comm_id)?;
+        Ok(Self {
+            comm_id: comm_id.to_string(),
+            target_name: target_name.to_string(),
+            connection: connection.downgrade(),
+            events,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.comm_id
+    }
+
+    pub fn target_name(&self) -> &str {
+        &self.target_name
+    }
+
+    /// `None` after the last [`KernelConnection`] is dropped.
+    pub fn connection(&self) -> Option<KernelConnection> {
+        self.connection.upgrade()
+    }
+
+    /// Sending half of the comm that can be used while the comm is being read.
+    pub fn sender(&self) -> CommSender {
+        CommSender {
+            comm_id: self.comm_id.clone(),
+            connection: self.connection.clone(),
+        }
+    }
+
+    /// Send a `comm_msg` to the kernel.
+    pub fn send<T: Serialize>(&self, data: &T) -> Result<()> {
+        self.sender().send(data)
+    }
+
+    /// Send a `comm_msg` with binary buffers to the kernel.
+    pub fn send_with_buffers<T: Serialize>(&self, data: &T, buffers: &[Bytes]) -> Result<()> {
+        self.sender().send_with_buffers(data, buffers)
+    }
+
+    /// Send a `comm_close` to the kernel.
+    pub fn close<T: Serialize>(self, data: &T) -> Result<()> {
+        self.sender().close(data)
+    }
+}
+
+#[derive(Clone)]
+pub struct CommSender {
+    comm_id: String,
+    connection: WeakKernelConnection,
+}
+
+impl CommSender {
+    pub fn comm_id(&self) -> &str {
+        &self.comm_id
+    }
+
+    fn connection(&self) -> Result<KernelConnection> {
+        self.connection
+            .upgrade()
+            .ok_or(JupyterApiError::KernelConnectionClosed)
+    }
+
+    /// Send a `comm_msg` to the kernel.
+    pub fn send<T: Serialize>(&self, data: &T) -> Result<()> {
+        self.send_with_buffers(data, &[])
+    }
+
+    /// Send a `comm_msg` with binary buffers to the kernel.
+    pub fn send_with_buffers<T: Serialize>(&self, data: &T, buffers: &[Bytes]) -> Result<()> {
+        let content = CommMsgContent {
+            comm_id: self.comm_id.clone(),
+            data: serde_json::to_value(data)?,
+        };
+        self.connection()?
+            .send_message_with_buffers(&KernelRequest::new(MessageType::CommMsg, content), buffers)
+    }
+
+    /// Send a `comm_close` to the kernel.
+    pub fn close<T: Serialize>(&self, data: &T) -> Result<()> {
+        let content = CommMsgContent {
+            comm_id: self.comm_id.clone(),
+            data: serde_json::to_value(data)?,
+        };
+        self.connection()?
+            .send_message(&KernelRequest::new(MessageType::CommClose, content))
+    }
+}
+
+impl Stream for Comm {
+    type Item = CommEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Comm {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.upgrade() {
+            connection.unregister_comm(&self.comm_id);
+        }
+    }
+}
+
+impl KernelConnection {
+    /// Open a comm to the `target_name` registered in the kernel.
+    pub fn open_comm<T: Serialize>(&self, target_name: &str, data: &T) -> Result<Comm> {
+        let comm_id = Uuid::new_v4().to_string();
+        let comm = Comm::attach(self, &comm_id, target_name)?;
+        let content = CommOpenContent {
+            comm_id,
+            target_name: target_name.to_string(),
+            data: serde_json::to_value(data)?,
+            target_module: None,
+        };
+        self.send_message(&KernelRequest::new(MessageType::CommOpen, content))?;
+        Ok(comm)
+    }
+
+    /// Handle the comms that the kernel opens to `target_name`.
+    /// The comms to the targets without a handler are closed.
+    pub fn register_comm_target<F>(&self, target_name: &str, handler: F)
+    where
+        F: Fn(Comm, CommMessage) + Send + Sync + 'static,
+    {
+        self.set_comm_target(target_name, Some(Arc::new(handler)));
+    }
+
+    pub fn unregister_comm_target(&self, target_name: &str) {
+        self.set_comm_target(target_name, None);
+    }
+
+    /// Comms open in the kernel, keyed by `comm_id`. All targets if `target_name` is `None`.
+    pub async fn comm_info(&self, target_name: Option<&str>) -> Result<HashMap<String, CommInfo>> {
+        let content = CommInfoRequestContent {
+            target_name: target_name.map(|v| v.to_string()),
+        };
+        let reply: CommInfoReply = self
+            .request(
+                MessageType::CommInfoRequest,
+                MessageType::CommInfoReply,
+                content,
+                None,
+            )
+            .await?;
+        Ok(reply.comms)
+    }
+}
@@ -0,0 +1,881 @@
+use super::comm::{Comm, CommEvent, CommMessage, CommTargetHandler};
+use super::execution::ExecutionStream;
+use super::types::*;
+use super::{KernelClientConfig, KernelWebSocket, WaitResult, WaitResultResponse};
+use super::{Result, DEFAULT_TIMEOUT_SEC};
+use crate::client::error::JupyterApiError;
+use bytes::Bytes;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::sink::SinkExt;
+use futures::stream::Stream;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// A websocket to the kernel kept open across requests.
+///
+/// The messages from the kernel are routed to the request whose `msg_id` and session are in their `parent_header`,
+/// so that many requests can run over the connection concurrently,
+/// and the outputs of the executions by the other clients never mix into the replies.
+/// Clones share the same websocket, which is closed when the last clone is dropped.
+#[derive(Clone)]
+pub struct KernelConnection {
+    inner: Arc<ConnectionInner>,
+}
+
+struct ConnectionInner {
+    outgoing: UnboundedSender<Message>,
+    router: Arc<Router>,
+    config: KernelClientConfig,
+}
+
+/// A reference to a connection that does not keep its websocket open.
+#[derive(Clone)]
+pub(super) struct WeakKernelConnection {
+    inner: Weak<ConnectionInner>,
+}
+
+impl WeakKernelConnection {
+    /// `None` after the last [`KernelConnection`] is dropped.
+    pub(super) fn upgrade(&self) -> Option<KernelConnection> {
+        self.inner.upgrade().map(|inner| KernelConnection { inner })
+    }
+}
+
+#[derive(Default)]
+struct Router {
+    state: Mutex<RouterState>,
+    /// To hand the comms opened by the kernel to their handlers.
+    connection: Weak<ConnectionInner>,
+}
+
+#[derive(Default)]
+struct RouterState {
+    closed: bool,
+    /// Keyed by the `msg_id` of the requests.
+    waiters: HashMap<String, Waiter>,
+    subscribers: Vec<UnboundedSender<KernelResponse>>,
+    /// Keyed by `comm_id`.
+    comms: HashMap<String, UnboundedSender<CommEvent>>,
+    /// Handlers of the comms opened by the kernel, keyed by `target_name`.
+    comm_targets: HashMap<String, CommTargetHandler>,
+    /// Receivers of the `debug_event`s.
+    debug_subscribers: Vec<UnboundedSender<Value>>,
+    /// Dropped when the connection is closed.
+    close_waiters: Vec<oneshot::Sender<()>>,
+}
+
+struct Waiter {
+    session: String,
+    sender: UnboundedSender<KernelResponse>,
+}
+
+impl RouterState {
+    /// `msg_id` of the pending request of this connection that the message is a reply to.
+    fn waiter_msg_id(&self, parent_header: &ParentHeader) -> Option<String> {
+        parent_header.msg_id.clone().filter(|msg_id| {
+            matches!(self.waiters.get(msg_id),
+                Some(waiter) if parent_header.is_reply_to(msg_id, &waiter.session))
+        })
+    }
+}
+
+impl Router {
+    fn register(&self, msg_id: &str, session: &str) -> Result<UnboundedReceiver<KernelResponse>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(JupyterApiError::KernelConnectionClosed);
+        }
+        let (sender, receiver) = mpsc::unbounded();
+        state.waiters.insert(
+            msg_id.to_string(),
+            Waiter {
+                session: session.to_string(),
+                sender,
+            },
+        );
+        Ok(receiver)
+    }
+
+    fn unregister(&self, msg_id: &str) {
+        self.state.lock().unwrap().waiters.remove(msg_id);
+    }
+
+    fn subscribe(&self) -> UnboundedReceiver<KernelResponse> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut state = self.state.lock().unwrap();
+        if !state.closed {
+            state.subscribers.push(sender);
+        }
+        receiver
+    }
+
+    fn route(&self, text: &str) {
+        match serde_json::from_str(text) {
+            Ok(resp) => self.route_response(resp),
+            Err(e) => log::debug!("skip the unparsable kernel message: {e}"),
+        }
+    }
+
+    /// Route a message with binary buffers.
+    fn route_binary(&self, frame: Vec<u8>) {
+        let decoded = decode_binary_message(Bytes::from(frame)).and_then(|(message, buffers)| {
+            let mut resp: KernelResponse = serde_json::from_slice(&message)?;
+            resp.buffers = buffers;
+            Ok(resp)
+        });
+        match decoded {
+            Ok(resp) => self.route_response(resp),
+            Err(e) => log::debug!("skip the unparsable binary kernel message: {e}"),
+        }
+    }
+
+    fn route_response(&self, resp: KernelResponse) {
+        let resp = match self.route_comm(resp) {
+            Some(resp) => resp,
+            None => return,
+        };
+        let resp = match self.route_debug_event(resp) {
+            Some(resp) => resp,
+            None => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(msg_id) = state.waiter_msg_id(&resp.parent_header) {
+            if state.waiters[&msg_id].sender.unbounded_send(resp).is_err() {
+                state.waiters.remove(&msg_id);
+            }
+            return;
+        }
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.unbounded_send(resp.clone()).is_ok());
+    }
+
+    fn register_comm(&self, comm_id: &str) -> Result<UnboundedReceiver<CommEvent>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(JupyterApiError::KernelConnectionClosed);
+        }
+        let (sender, receiver) = mpsc::unbounded();
+        state.comms.insert(comm_id.to_string(), sender);
+        Ok(receiver)
+    }
+
+    /// Deliver the comm messages to the comms.
+    /// Returns the message back if it is not for a comm of this connection.
+    ///
+    /// The `comm_open`s are broadcast to all the frontends of the kernel,
+    /// so only the comms opened by the requests of this connection are closed when there is no handler.
+    fn route_comm(&self, resp: KernelResponse) -> Option<KernelResponse> {
+        match resp.msg_type {
+            MessageType::CommOpen => {
+                let open = match resp.as_comm_open_content() {
+                    Ok(Some(open)) => open,
+                    _ => return Some(resp),
+                };
+                let (handler, own) = {
+                    let state = self.state.lock().unwrap();
+                    (
+                        state.comm_targets.get(&open.target_name).cloned(),
+                        state.waiter_msg_id(&resp.parent_header).is_some(),
+                    )
+                };
+                if handler.is_none() && !own {
+                    return Some(resp);
+                }
+                let connection = match self.connection.upgrade() {
+                    Some(inner) => KernelConnection { inner },
+                    None => return None,
+                };
+
+                match handler {
+                    Some(handler) => {
+                        match Comm::attach(&connection, &open.comm_id, &open.target_name) {
+                            Ok(comm) => handler(comm, CommMessage::new(open.data, &resp)),
+                            Err(e) => log::debug!("failed to attach the comm: {e}"),
+                        }
+                    }
+                    None => {
+                        log::debug!("close the comm to unknown target {}", open.target_name);
+                        let content = CommMsgContent {
+                            comm_id: open.comm_id,
+                            data: Value::Object(Default::default()),
+                        };
+                        connection
+                            .send_message(&KernelRequest::new(MessageType::CommClose, content))
+                            .ok();
+                    }
+                }
+                None
+            }
+
+            MessageType::CommMsg | MessageType::CommClose => {
+                let msg = match resp.as_comm_msg_content() {
+                    Ok(Some(msg)) => msg,
+                    _ => return Some(resp),
+                };
+                let mut state = self.state.lock().unwrap();
+                let sender = match state.comms.get(&msg.comm_id) {
+                    Some(sender) => sender,
+                    None => {
+                        drop(state);
+                        return Some(resp);
+                    }
+                };
+
+                let closing = resp.msg_type == MessageType::CommClose;
+                let message = CommMessage::new(msg.data, &resp);
+                let event = if closing {
+                    CommEvent::Close(message)
+                } else {
+                    CommEvent::Msg(message)
+                };
+                if sender.unbounded_send(event).is_err() || closing {
+                    state.comms.remove(&msg.comm_id);
+                }
+                None
+            }
+
+            _ => Some(resp),
+        }
+    }
+
+    /// Deliver the `debug_event`s to the debug sessions.
+    /// Returns the message back if it is not a debug event or there is no debug session.
+    fn route_debug_event(&self, resp: KernelResponse) -> Option<KernelResponse> {
+        if resp.msg_type != MessageType::DebugEvent {
+            return Some(resp);
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.debug_subscribers.is_empty() {
+            return Some(resp);
+        }
+        let event = resp.content.unwrap_or_default();
+        state
+            .debug_subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+        None
+    }
+
+    /// Drop all the senders so that the receivers end.
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.waiters.clear();
+        state.subscribers.clear();
+        state.comms.clear();
+        state.debug_subscribers.clear();
+        state.close_waiters.clear();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+}
+
+/// The messages from the kernel in reply to a request.
+/// Ends when the connection is closed.
+pub struct KernelReplies {
+    msg_id: String,
+    receiver: UnboundedReceiver<KernelResponse>,
+    router: Arc<Router>,
+}
+
+impl KernelReplies {
+    /// `msg_id` of the request.
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+}
+
+impl Stream for KernelReplies {
+    type Item = KernelResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for KernelReplies {
+    fn drop(&mut self) {
+        self.router.unregister(&self.msg_id);
+    }
+}
+
+impl KernelConnection {
+    pub(crate) fn new(socket: KernelWebSocket, config: KernelClientConfig) -> Self {
+        let (outgoing, outgoing_receiver) = mpsc::unbounded();
+        let inner = Arc::new_cyclic(|connection| ConnectionInner {
+            outgoing,
+            router: Arc::new(Router {
+                connection: connection.clone(),
+                ..Default::default()
+            }),
+            config,
+        });
+        tokio::spawn(run_socket(socket, outgoing_receiver, inner.router.clone()));
+        Self { inner }
+    }
+
+    pub(super) fn downgrade(&self) -> WeakKernelConnection {
+        WeakKernelConnection {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.router.is_closed()
+    }
+
+    /// Wait until the connection is closed.
+    pub async fn closed(&self) {
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut state = self.inner.router.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            state.close_waiters.push(sender);
+        }
+        receiver.await.ok();
+    }
+
+    /// Messages that are neither replies to the pending requests nor to the comms of this connection,
+    /// e.g. the outputs of the executions by the other clients of the kernel.
+    pub fn subscribe(&self) -> impl Stream<Item = KernelResponse> {
+        self.inner.router.subscribe()
+    }
+
+    /// Send the request and receive the messages whose parent is the request.
+    pub fn send_request<Req: Serialize>(&self, request: &Req) -> Result<KernelReplies> {
+        let message = self.encode_request(request)?;
+        let header_field = |name: &str| {
+            message
+                .pointer(&format!("/header/{name}"))
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .ok_or_else(|| JupyterApiError::InvalidRequest(format!("no {name} in the header")))
+        };
+        let msg_id = header_field("msg_id")?;
+        let session = header_field("session")?;
+
+        let receiver = self.inner.router.register(&msg_id, &session)?;
+        let replies = KernelReplies {
+            msg_id,
+            receiver,
+            router: self.inner.router.clone(),
+        };
+        self.inner
+            .outgoing
+            .unbounded_send(encode_message(&message, &[])?)
+            .map_err(|_| JupyterApiError::KernelConnectionClosed)?;
+        Ok(replies)
+    }
+
+    /// `timeout`, or the configured default to wait for the kernel messages.
+    pub(super) fn kernel_timeout(&self, timeout: Option<Duration>) -> Duration {
+        timeout
+            .or(self.inner.config.kernel_timeout)
+            .unwrap_or(Duration::from_secs(DEFAULT_TIMEOUT_SEC))
+    }
+
+    /// Send the request without waiting for the replies.
+    /// The replies are delivered to [`Self::subscribe`].
+    pub fn send_message<Req: Serialize>(&self, request: &Req) -> Result<()> {
+        self.send_message_with_buffers(request, &[])
+    }
+
+    /// Send the request with binary buffers without waiting for the replies.
+    pub fn send_message_with_buffers<Req: Serialize>(
+        &self,
+        request: &Req,
+        buffers: &[Bytes],
+    ) -> Result<()> {
+        let message = self.encode_request(request)?;
+        self.inner
+            .outgoing
+            .unbounded_send(encode_message(&message, buffers)?)
+            .map_err(|_| JupyterApiError::KernelConnectionClosed)
+    }
+
+    pub(super) fn register_comm(&self, comm_id: &str) -> Result<UnboundedReceiver<CommEvent>> {
+        self.inner.router.register_comm(comm_id)
+    }
+
+    pub(super) fn unregister_comm(&self, comm_id: &str) {
+        self.inner
+            .router
+            .state
+            .lock()
+            .unwrap()
+            .comms
+            .remove(comm_id);
+    }
+
+    pub(super) fn subscribe_debug_events(&self) -> UnboundedReceiver<Value> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut state = self.inner.router.state.lock().unwrap();
+        if !state.closed {
+            state.debug_subscribers.push(sender);
+        }
+        receiver
+    }
+
+    pub(super) fn set_comm_target(&self, target_name: &str, handler: Option<CommTargetHandler>) {
+        let mut state = self.inner.router.state.lock().unwrap();
+        match handler {
+            Some(handler) => state.comm_targets.insert(target_name.to_string(), handler),
+            None => state.comm_targets.remove(target_name),
+        };
+    }
+
+    /// Serialize the request with the configured username.
+    /// The `parent_header` is of another message, so it is sent as is.
+    fn encode_request<Req: Serialize>(&self, request: &Req) -> Result<Value> {
+        let mut message = serde_json::to_value(request)?;
+        if let Some(username) = &self.inner.config.username {
+            if let Some(header) = message.get_mut("header").and_then(|v| v.as_object_mut()) {
+                header.insert("username".to_string(), username.clone().into());
+            }
+        }
+        Ok(message)
+    }
+
+    pub async fn run_code(
+        &self,
+        request: KernelCodeRequest,
+        timeout: Option<Duration>,
+    ) -> Result<CompositeKernelResponses> {
+        self.check_stdin(&request)?;
+        self.run_and_wait_message(request, super::WaitResultAndDisplayData::default(), timeout)
+            .await
+    }
+
+    /// Run the code, yielding the outputs as they arrive.
+    pub fn execute_stream(&self, request: impl Into<KernelCodeRequest>) -> Result<ExecutionStream> {
+        let request = request.into();
+        self.check_stdin(&request)?;
+        let replies = self.send_request(&request)?;
+        Ok(ExecutionStream::new(replies, self.clone()))
+    }
+
+    fn check_stdin(&self, request: &KernelCodeRequest) -> Result<()> {
+        if request.content().allows_stdin() && self.inner.config.input_provider.is_none() {
+            return Err(JupyterApiError::InvalidRequest(
+                "allow_stdin requires an input provider".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Ask the input provider and send the `input_reply` to the `input_request`.
+    ///
+    /// If the provider fails, an empty `input_reply` is sent before returning the error,
+    /// so that the kernel is not left blocked in `input()`.
+    pub(super) fn answer_input(&self, request: KernelResponse) -> BoxFuture<'static, Result<()>> {
+        let connection = self.clone();
+        Box::pin(async move {
+            let value = connection.ask_input(&request).await;
+            let reply = KernelRequest::reply_to(
+                MessageType::InputReply,
+                Channel::Stdin,
+                &request.header,
+                InputReplyContent {
+                    value: value.as_ref().cloned().unwrap_or_default(),
+                },
+            );
+            connection.send_message(&reply)?;
+            value.map(|_| ())
+        })
+    }
+
+    async fn ask_input(&self, request: &KernelResponse) -> Result<String> {
+        let content = request.as_input_request_content()?.unwrap_or_default();
+        let provider = match &self.inner.config.input_provider {
+            Some(provider) => provider.clone(),
+            None => return Err(JupyterApiError::InputProviderNotSet(content.prompt)),
+        };
+        provider.input(content).await
+    }
+
+    /// Send the request and wait until `wait_result` completes.
+    /// The timeout covers the whole wait, including answering the `input_request`s.
+    pub async fn run_and_wait_message<F, Req: Serialize>(
+        &self,
+        request: Req,
+        mut wait_result: F,
+        timeout: Option<Duration>,
+    ) -> Result<CompositeKernelResponses>
+    where
+        F: WaitResult,
+    {
+        let mut replies = self.send_request(&request)?;
+
+        let wait = async {
+            while let Some(resp) = replies.next().await {
+                log::debug!("receipt_message: {resp:?}");
+                if resp.msg_type == MessageType::InputRequest {
+                    self.answer_input(resp).await?;
+                    continue;
+                }
+
+                match wait_result.check(resp) {
+                    Some(WaitResultResponse::KernelResponse(response)) => {
+                        return Ok(Some(response))
+                    }
+                    Some(WaitResultResponse::Completed) => return Ok(None),
+                    None => {}
+                }
+            }
+            Err(JupyterApiError::KernelConnectionClosed)
+        };
+        let response = match tokio::time::timeout(self.kernel_timeout(timeout), wait).await {
+            Ok(response) => response?,
+            Err(_) => {
+                log::debug!("timeout.");
+                return Err(JupyterApiError::KernelMessageTimeout);
+            }
+        };
+        if let Some(response) = response {
+            return Ok(CompositeKernelResponses::SingleResponse(response));
+        }
+
+        match wait_result.latest_result() {
+            (None, None) => Err(JupyterApiError::EmptyResponse),
+            (Some(latest_result), None) => {
+                Ok(CompositeKernelResponses::SingleResponse(latest_result))
+            }
+            (None, Some(stream)) => Ok(CompositeKernelResponses::MultipleResponse(stream)),
+            (Some(latest_result), Some(mut stream)) => {
+                stream.push(latest_result);
+                Ok(CompositeKernelResponses::MultipleResponse(stream))
+            }
+        }
+    }
+}
+
+/// Own the websocket until the connection is dropped or closed by the kernel.
+async fn run_socket(
+    mut socket: KernelWebSocket,
+    mut outgoing: UnboundedReceiver<Message>,
+    router: Arc<Router>,
+) {
+    loop {
+        tokio::select! {
+            message = outgoing.next() => match message {
+                Some(message) => {
+                    if let Err(e) = socket.send(message).await {
+                        log::debug!("failed to send to the kernel: {e}");
+                        break;
+                    }
+                }
+                None => {
+                    socket.close(None).await.ok();
+                    break;
+                }
+            },
+
+            received = socket.next() => match received {
+                Some(Ok(Message::Text(text))) => router.route(&text),
+                Some(Ok(Message::Binary(frame))) => router.route_binary(frame),
+                Some(Ok(Message::Ping(body))) => {
+                    socket.send(Message::Pong(body)).await.ok();
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    log::debug!("kernel connection error: {e}");
+                    break;
+                }
+            },
+        }
+    }
+    router.close();
+}
+
+/// A text frame, or a binary frame if there are buffers.
+fn encode_message(message: &Value, buffers: &[Bytes]) -> Result<Message> {
+    if buffers.is_empty() {
+        return Ok(Message::Text(serde_json::to_string(message)?));
+    }
+    Ok(Message::Binary(encode_binary_message(
+        &serde_json::to_vec(message)?,
+        buffers,
+    )))
+}
+
+/// Binary frame of the Jupyter server websocket:
+/// the number of parts and the offset of each part as big endian `u32`s,
+/// followed by the JSON message and the buffers.
+fn encode_binary_message(message: &[u8], buffers: &[Bytes]) -> Vec<u8> {
+    let parts: Vec<&[u8]> = std::iter::once(message)
+        .chain(buffers.iter().map(|v| v.as_ref()))
+        .collect();
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&(parts.len() as u32).to_be_bytes());
+    let mut offset = 4 * (parts.len() + 1);
+    for part in &parts {
+        frame.extend_from_slice(&(offset as u32).to_be_bytes());
+        offset += part.len();
+    }
+    for part in parts {
+        frame.extend_from_slice(part);
+    }
+    frame
+}
+
+/// Split a binary frame into the JSON message and the buffers.
+fn decode_binary_message(frame: Bytes) -> Result<(Bytes, Vec<Bytes>)> {
+    let invalid = || JupyterApiError::KernelMessageError("invalid binary message".to_string());
+    let read_u32 = |at: usize| -> Result<usize> {
+        let bytes = frame.get(at..at + 4).ok_or_else(invalid)?;
+        Ok(u32::from_be_bytes(bytes.try_into().map_err(|_| invalid())?) as usize)
+    };
+
+    let nparts = read_u32(0)?;
+    if nparts == 0 {
+        return Err(invalid());
+    }
+    let mut offsets = (1..=nparts)
+        .map(|i| read_u32(4 * i))
+        .collect::<Result<Vec<_>>>()?;
+    offsets.push(frame.len());
+    if offsets.windows(2).any(|w| w[0] > w[1]) {
+        return Err(invalid());
+    }
+
+    let mut parts = offsets.windows(2).map(|w| frame.slice(w[0]..w[1]));
+    let message = parts.next().ok_or_else(invalid)?;
+    Ok((message, parts.collect()))
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::super::{InputProvider, KernelStream};
+    use super::*;
+    use tokio::io::DuplexStream;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    /// A connection and the kernel end of its websocket.
+    pub(in crate::client::kernel) async fn fake_kernel(
+        config: KernelClientConfig,
+    ) -> (KernelConnection, WebSocketStream<DuplexStream>) {
+        let (client, kernel) = tokio::io::duplex(64 * 1024);
+        let client: Box<dyn KernelStream> = Box::new(client);
+        let socket =
+            WebSocketStream::from_raw_socket(MaybeTlsStream::Plain(client), Role::Client, None)
+                .await;
+        let kernel = WebSocketStream::from_raw_socket(kernel, Role::Server, None).await;
+        (KernelConnection::new(socket, config), kernel)
+    }
+
+    struct FailingInput;
+
+    impl InputProvider for FailingInput {
+        fn input(&self, _: InputRequestContent) -> BoxFuture<'static, Result<String>> {
+            Box::pin(async { Err(JupyterApiError::InputError("no terminal".to_string())) })
+        }
+    }
+
+    struct NoInput;
+
+    impl InputProvider for NoInput {
+        fn input(&self, _: InputRequestContent) -> BoxFuture<'static, Result<String>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    /// Receive the execute request and ask for input in reply to it.
+    async fn request_input(kernel: &mut WebSocketStream<DuplexStream>) {
+        let request = match kernel.next().await {
+            Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text).unwrap(),
+            message => panic!("unexpected {message:?}"),
+        };
+        assert_eq!(request["header"]["msg_type"], "execute_request");
+        let input_request = serde_json::json!({
+            "header": {"username": "u", "session": "kernel", "msg_type": "input_request", "version": "5.3", "msg_id": "input", "date": "d"},
+            "msg_id": "input",
+            "msg_type": "input_request",
+            "parent_header": request["header"],
+            "metadata": {},
+            "content": {"prompt": "name: ", "password": false},
+            "buffers": [],
+            "channel": "stdin",
+        });
+        kernel
+            .send(Message::Text(input_request.to_string()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stdin_without_provider() {
+        let (connection, _kernel) = fake_kernel(KernelClientConfig::default()).await;
+        let request: KernelCodeRequest =
+            KernelCodeContent::from("input()").allow_stdin(true).into();
+        assert!(matches!(
+            connection.run_code(request.clone(), None).await,
+            Err(JupyterApiError::InvalidRequest(_))
+        ));
+        assert!(connection.execute_stream(request).is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_input_reply_on_provider_error() {
+        let config = KernelClientConfig {
+            input_provider: Some(Arc::new(FailingInput)),
+            ..Default::default()
+        };
+        let (connection, mut kernel) = fake_kernel(config).await;
+        let request = KernelCodeContent::from("input()").allow_stdin(true).into();
+        let kernel = tokio::spawn(async move {
+            request_input(&mut kernel).await;
+            match kernel.next().await {
+                Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text).unwrap(),
+                message => panic!("unexpected {message:?}"),
+            }
+        });
+
+        assert!(matches!(
+            connection.run_code(request, None).await,
+            Err(JupyterApiError::InputError(_))
+        ));
+        let reply = kernel.await.unwrap();
+        assert_eq!(reply["header"]["msg_type"], "input_reply");
+        assert_eq!(reply["parent_header"]["msg_id"], "input");
+        assert_eq!(reply["content"]["value"], "");
+    }
+
+    #[tokio::test]
+    async fn timeout_while_waiting_for_input() {
+        let config = KernelClientConfig {
+            input_provider: Some(Arc::new(NoInput)),
+            ..Default::default()
+        };
+        let (connection, mut kernel) = fake_kernel(config).await;
+        let request: KernelCodeRequest =
+            KernelCodeContent::from("input()").allow_stdin(true).into();
+        let timeout = Some(Duration::from_millis(50));
+
+        let kernel = tokio::spawn(async move {
+            request_input(&mut kernel).await;
+            request_input(&mut kernel).await;
+            kernel
+        });
+        // fails instead of hanging if the timeout does not cover the input
+        let guard = Duration::from_secs(5);
+        assert!(matches!(
+            tokio::time::timeout(guard, connection.run_code(request.clone(), timeout)).await,
+            Ok(Err(JupyterApiError::KernelMessageTimeout))
+        ));
+        assert!(matches!(
+            tokio::time::timeout(guard, connection.execute(request, timeout)).await,
+            Ok(Err(JupyterApiError::KernelMessageTimeout))
+        ));
+        kernel.await.unwrap();
+    }
+
+    fn message(parent_header: &str) -> String {
+        format!(
+            r#"{{
+                "header": {{"username": "u", "session": "kernel", "msg_type": "stream", "version": "5.3", "msg_id": "m", "date": "d"}},
+                "msg_id": "m",
+                "msg_type": "stream",
+                "parent_header": {parent_header},
+                "metadata": {{}},
+                "content": {{"name": "stdout", "text": "1"}},
+                "buffers": [],
+                "channel": "iopub"
+            }}"#
+        )
+    }
+
+    #[test]
+    fn route_by_parent_header() {
+        let router = Router::default();
+        let mut replies = router.register("req", "ours").unwrap();
+        let mut others = router.subscribe();
+
+        router.route(&message(r#"{"msg_id": "req", "session": "ours"}"#));
+        router.route(&message(r#"{"msg_id": "req", "session": "theirs"}"#));
+        router.route(&message(r#"{"msg_id": "other", "session": "ours"}"#));
+        router.route(&message("{}"));
+        router.route("not a message");
+        router.close();
+
+        let replies: Vec<_> = futures::executor::block_on_stream(&mut replies).collect();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].parent_header.session, "ours");
+
+        let others: Vec<_> = futures::executor::block_on_stream(&mut others).collect();
+        assert_eq!(others.len(), 3);
+        assert_eq!(others[2].parent_header, ParentHeader::default());
+    }
+
+    #[test]
+    fn route_comm_messages() {
+        let router = Router::default();
+        let mut events = router.register_comm("comm").unwrap();
+        let mut others = router.subscribe();
+
+        let comm_message = |msg_type: &str, comm_id: &str| {
+            message(r#"{}"#)
+                .replace(
+                    r#""msg_type": "stream""#,
+                    &format!(r#""msg_type": "{msg_type}""#),
+                )
+                .replace(
+                    r#"{"name": "stdout", "text": "1"}"#,
+                    &format!(r#"{{"comm_id": "{comm_id}", "data": {{"value": 1}}}}"#),
+                )
+        };
+        router.route(&comm_message("comm_msg", "comm"));
+        router.route(&comm_message("comm_msg", "unknown"));
+        // opened for another frontend
+        router.route(&comm_message("comm_open", "other").replace(
+            r#""comm_id": "other","#,
+            r#""comm_id": "other", "target_name": "jupyter.widget","#,
+        ));
+        router.route(&comm_message("comm_close", "comm"));
+        router.route(&comm_message("comm_msg", "comm"));
+        router.close();
+
+        let events: Vec<_> = futures::executor::block_on_stream(&mut events).collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], CommEvent::Msg(msg) if msg.data["value"] == 1));
+        assert!(matches!(&events[1], CommEvent::Close(_)));
+
+        let others: Vec<_> = futures::executor::block_on_stream(&mut others).collect();
+        assert_eq!(others.len(), 3);
+        assert_eq!(others[1].msg_type, MessageType::CommOpen);
+    }
+
+    #[test]
+    fn binary_messages() {
+        let buffers = vec![Bytes::from_static(b"\x00\x01"), Bytes::new()];
+        let frame = encode_binary_message(b"{}", &buffers);
+        assert_eq!(&frame[..4], &3u32.to_be_bytes());
+        assert_eq!(
+            decode_binary_message(Bytes::from(frame)).unwrap(),
+            (Bytes::from_static(b"{}"), buffers.clone())
+        );
+        assert!(decode_binary_message(Bytes::from_static(b"\x00\x00\x00\x02")).is_err());
+
+        let router = Router::default();
+        let mut others = router.subscribe();
+        router.route_binary(encode_binary_message(message("{}").as_bytes(), &buffers));
+        router.close();
+        let others: Vec<_> = futures::executor::block_on_stream(&mut others).collect();
+        assert_eq!(others[0].buffers, buffers);
+    }
+}
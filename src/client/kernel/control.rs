@@ -0,0 +1,73 @@
+use super::connection::KernelConnection;
+use super::types::*;
+use super::{KernelApiClient, Result};
+
+impl KernelConnection {
+    /// Interrupt the kernel over the control channel.
+    /// Works for the kernels with `interrupt_mode: message` as well as `signal`.
+    pub async fn interrupt(&self) -> Result<InterruptReply> {
+        self.wait_reply(
+            interrupt_request(),
+            MessageType::InterruptReply,
+            false,
+            None,
+        )
+        .await
+    }
+
+    /// Shut down or restart the kernel over the control channel.
+    pub async fn shutdown(&self, restart: bool) -> Result<ShutdownReply> {
+        self.wait_reply(
+            shutdown_request(restart),
+            MessageType::ShutdownReply,
+            false,
+            None,
+        )
+        .await
+    }
+}
+
+fn interrupt_request() -> KernelRequest<InterruptRequestContent> {
+    KernelRequest::new(
+        MessageType::InterruptRequest,
+        InterruptRequestContent::default(),
+    )
+    .channel(Channel::Control)
+}
+
+fn shutdown_request(restart: bool) -> KernelRequest<ShutdownRequestContent> {
+    KernelRequest::new(
+        MessageType::ShutdownRequest,
+        ShutdownRequestContent { restart },
+    )
+    .channel(Channel::Control)
+}
+
+impl KernelApiClient {
+    pub async fn interrupt(&self) -> Result<InterruptReply> {
+        self.connect().await?.interrupt().await
+    }
+
+    pub async fn shutdown(&self, restart: bool) -> Result<ShutdownReply> {
+        self.connect().await?.shutdown(restart).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn control_requests() {
+        let interrupt = serde_json::to_value(interrupt_request()).unwrap();
+        assert_eq!(interrupt["channel"], "control");
+        assert_eq!(interrupt["header"]["msg_type"], "interrupt_request");
+        assert_eq!(interrupt["content"], json!({}));
+
+        let shutdown = serde_json::to_value(shutdown_request(true)).unwrap();
+        assert_eq!(shutdown["channel"], "control");
+        assert_eq!(shutdown["header"]["msg_type"], "shutdown_request");
+        assert_eq!(shutdown["content"], json!({"restart": true}));
+    }
+}
@@ -0,0 +1,234 @@
+use super::connection::KernelConnection;
+use super::types::*;
+use super::Result;
+use crate::client::error::JupyterApiError;
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+const CONTENT_LENGTH_HEADER: &str = "Content-Length";
+
+/// Debug Adapter Protocol session with the kernel over `debug_request`, `debug_reply` and `debug_event`.
+pub struct DebugSession {
+    connection: KernelConnection,
+    events: UnboundedReceiver<Value>,
+    seq: AtomicI64,
+}
+
+impl KernelConnection {
+    /// Start receiving the `debug_event`s of the kernel.
+    /// The kernel must support the debugger. (see [`KernelInfoReply::debugger`])
+    pub fn debug_session(&self) -> DebugSession {
+        DebugSession {
+            events: self.subscribe_debug_events(),
+            connection: self.clone(),
+            seq: AtomicI64::new(1),
+        }
+    }
+}
+
+impl DebugSession {
+    /// Send a DAP request and return the DAP response.
+    pub async fn request(&self, command: &str, arguments: Value) -> Result<Value> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.send(json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }))
+        .await
+    }
+
+    /// Send a DAP request as is and return the DAP response.
+    pub async fn send(&self, dap_request: Value) -> Result<Value> {
+        send_debug_request(&self.connection, dap_request).await
+    }
+
+    /// The next DAP event from the kernel. `None` when the connection is closed.
+    pub async fn next_event(&mut self) -> Option<Value> {
+        self.events.next().await
+    }
+}
+
+async fn send_debug_request(connection: &KernelConnection, dap_request: Value) -> Result<Value> {
+    let request =
+        KernelRequest::new(MessageType::DebugRequest, dap_request).channel(Channel::Control);
+    connection
+        .wait_reply(request, MessageType::DebugReply, false, None)
+        .await
+}
+
+/// Local TCP server that forwards the DAP messages between an editor and the kernel.
+pub struct DapServer {
+    listener: TcpListener,
+    connection: KernelConnection,
+}
+
+impl DapServer {
+    pub async fn bind<A: ToSocketAddrs>(addr: A, connection: KernelConnection) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            connection,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the editors one at a time until the kernel connection is closed.
+    pub async fn serve(self) -> Result<()> {
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = self.listener.accept() => accepted?,
+                _ = self.connection.closed() => return Ok(()),
+            };
+            log::debug!("dap client connected from {peer}");
+            if let Err(e) = serve_client(stream, &self.connection).await {
+                log::debug!("dap client {peer} error: {e}");
+            }
+        }
+    }
+}
+
+async fn serve_client(stream: TcpStream, connection: &KernelConnection) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut session = connection.debug_session();
+
+    // reading a frame is not cancel safe, so read in another task
+    let (request_sender, mut requests) = mpsc::unbounded();
+    let reader_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(request)) = read_dap_message(&mut reader).await {
+            if request_sender.unbounded_send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (response_sender, mut responses) = mpsc::unbounded();
+    let result = loop {
+        tokio::select! {
+            request = requests.next() => match request {
+                Some(request) => {
+                    let connection = connection.clone();
+                    let response_sender = response_sender.clone();
+                    tokio::spawn(async move {
+                        let response = send_debug_request(&connection, request.clone())
+                            .await
+                            .unwrap_or_else(|e| {
+                                log::debug!("debug request failed: {e}");
+                                dap_error_response(&request, &e)
+                            });
+                        response_sender.unbounded_send(response).ok();
+                    });
+                }
+                None => break Ok(()),
+            },
+            response = responses.next() => {
+                if let Some(response) = response {
+                    write_dap_message(&mut writer, &response).await?;
+                }
+            },
+            event = session.next_event() => match event {
+                Some(event) => write_dap_message(&mut writer, &event).await?,
+                None => break Err(JupyterApiError::KernelConnectionClosed),
+            },
+        }
+    };
+    reader_task.abort();
+    result
+}
+
+/// Failed DAP response to the request, so that the editor does not wait for it forever.
+fn dap_error_response(request: &Value, error: &JupyterApiError) -> Value {
+    json!({
+        "seq": 0,
+        "type": "response",
+        "request_seq": request.get("seq").cloned().unwrap_or_default(),
+        "command": request.get("command").cloned().unwrap_or_default(),
+        "success": false,
+        "message": error.to_string(),
+    })
+}
+
+/// Read a `Content-Length` framed DAP message. `None` at the end of the stream.
+pub async fn read_dap_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case(CONTENT_LENGTH_HEADER) {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        JupyterApiError::InvalidRequest("no Content-Length in the dap message".to_string())
+    })?;
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub async fn write_dap_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &Value,
+) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    writer
+        .write_all(format!("{CONTENT_LENGTH_HEADER}: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn dap_framing() {
+        let message = json!({"seq": 1, "type": "request", "command": "initialize"});
+        let mut buf = Vec::new();
+        write_dap_message(&mut buf, &message).await.unwrap();
+        write_dap_message(&mut buf, &json!({"seq": 2}))
+            .await
+            .unwrap();
+
+        let mut reader = buf.as_slice();
+        assert_eq!(read_dap_message(&mut reader).await.unwrap(), Some(message));
+        assert_eq!(
+            read_dap_message(&mut reader).await.unwrap(),
+            Some(json!({"seq": 2}))
+        );
+        assert_eq!(read_dap_message(&mut reader).await.unwrap(), None);
+    }
+
+    #[test]
+    fn error_response() {
+        let request = json!({"seq": 3, "type": "request", "command": "stackTrace"});
+        let response = dap_error_response(&request, &JupyterApiError::KernelMessageTimeout);
+        assert_eq!(response["type"], "response");
+        assert_eq!(response["request_seq"], 3);
+        assert_eq!(response["command"], "stackTrace");
+        assert_eq!(response["success"], false);
+        assert!(response["message"].as_str().unwrap().contains("timeout"));
+    }
+}
@@ -0,0 +1,178 @@
+use super::connection::{KernelConnection, KernelReplies};
+use super::output::{OutputArea, RenderedOutput};
+use super::types::*;
+use super::{is_idle_status, KernelApiClient, Result};
+use crate::client::error::JupyterApiError;
+use futures::future::BoxFuture;
+use futures::stream::Stream;
+use futures_util::StreamExt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::time::Duration;
+
+/// An output or state change of an execution.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionEvent {
+    Stream(StreamContent),
+    DisplayData(DisplayDataContent),
+    ExecuteResult(ExecuteResultContent),
+    Error(ErrorContent),
+    Status(StatusContent),
+    Reply(ExecuteReplyContent),
+    ClearOutput(ClearOutputContent),
+    /// Replaces the display of the same `display_id`.
+    UpdateDisplayData(DisplayDataContent),
+}
+
+impl ExecutionEvent {
+    /// `None` for the messages that are not execution events, e.g. `execute_input`.
+    pub fn from_response(resp: &KernelResponse) -> Result<Option<Self>> {
+        let event = match resp.msg_type {
+            MessageType::Stream => resp.as_stream_content()?.map(Self::Stream),
+            MessageType::DisplayData => resp.as_display_data_content()?.map(Self::DisplayData),
+            MessageType::ExecuteResult => {
+                resp.as_execute_result_content()?.map(Self::ExecuteResult)
+            }
+            MessageType::Error => resp.as_error_content()?.map(Self::Error),
+            MessageType::Status => resp.as_status_content()?.map(Self::Status),
+            MessageType::ExecuteReply => resp.as_execute_reply_content()?.map(Self::Reply),
+            MessageType::ClearOutput => resp.as_clear_output_content()?.map(Self::ClearOutput),
+            MessageType::UpdateDisplayData => {
+                resp.as_display_data_content()?.map(Self::UpdateDisplayData)
+            }
+            _ => None,
+        };
+        Ok(event)
+    }
+}
+
+/// The events of an execution as they arrive.
+/// Ends after both the reply and the idle status have arrived.
+pub struct ExecutionStream {
+    replies: KernelReplies,
+    connection: KernelConnection,
+    /// Answer to the `input_request` in progress.
+    pending_input: Option<BoxFuture<'static, Result<()>>>,
+    reply_received: bool,
+    idle_received: bool,
+    done: bool,
+}
+
+impl ExecutionStream {
+    pub(crate) fn new(replies: KernelReplies, connection: KernelConnection) -> Self {
+        Self {
+            replies,
+            connection,
+            pending_input: None,
+            reply_received: false,
+            idle_received: false,
+            done: false,
+        }
+    }
+
+    /// `msg_id` of the execute request.
+    pub fn msg_id(&self) -> &str {
+        self.replies.msg_id()
+    }
+}
+
+impl Stream for ExecutionStream {
+    type Item = Result<ExecutionEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            if let Some(pending_input) = self.pending_input.as_mut() {
+                match pending_input.as_mut().poll(cx) {
+                    Poll::Ready(result) => {
+                        self.pending_input = None;
+                        if let Err(e) = result {
+                            self.done = true;
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            let resp = match self.replies.poll_next_unpin(cx) {
+                Poll::Ready(Some(resp)) => resp,
+                Poll::Ready(None) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(JupyterApiError::KernelConnectionClosed)));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if resp.msg_type == MessageType::InputRequest {
+                let pending_input = self.connection.answer_input(resp);
+                self.pending_input = Some(pending_input);
+                continue;
+            }
+            if resp.msg_type == MessageType::ExecuteReply {
+                self.reply_received = true;
+            }
+            if is_idle_status(&resp) {
+                self.idle_received = true;
+            }
+            self.done = self.reply_received && self.idle_received;
+
+            match ExecutionEvent::from_response(&resp) {
+                Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                Ok(None) => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+/// The rendered outputs and the reply of an execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionOutput {
+    pub outputs: Vec<RenderedOutput>,
+    pub reply: Option<ExecuteReplyContent>,
+}
+
+impl KernelConnection {
+    /// Run the code and render its outputs as a notebook does.
+    pub async fn execute(
+        &self,
+        request: impl Into<KernelCodeRequest>,
+        timeout: Option<Duration>,
+    ) -> Result<ExecutionOutput> {
+        let mut events = self.execute_stream(request)?;
+        let render = async {
+            let mut area = OutputArea::default();
+            let mut reply = None;
+            while let Some(event) = events.next().await {
+                let event = event?;
+                area.apply(&event);
+                if let ExecutionEvent::Reply(content) = event {
+                    reply = Some(content);
+                }
+            }
+            Ok(ExecutionOutput {
+                outputs: area.into_outputs(),
+                reply,
+            })
+        };
+        match tokio::time::timeout(self.kernel_timeout(timeout), render).await {
+            Ok(result) => result,
+            Err(_) => Err(JupyterApiError::KernelMessageTimeout),
+        }
+    }
+}
+
+impl KernelApiClient {
+    /// Run the code over a new connection and render its outputs as a notebook does.
+    pub async fn execute(
+        &self,
+        request: impl Into<KernelCodeRequest>,
+        timeout: Option<Duration>,
+    ) -> Result<ExecutionOutput> {
+        self.connect().await?.execute(request, timeout).await
+    }
+}
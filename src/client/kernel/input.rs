@@ -0,0 +1,38 @@
+use super::types::InputRequestContent;
+use super::Result;
+use crate::client::error::JupyterApiError;
+use futures::future::BoxFuture;
+use std::io::{BufRead, Write};
+
+/// Answers the `input_request`s of the kernel, e.g. for `input()` and `getpass()`.
+pub trait InputProvider: Send + Sync {
+    fn input(&self, request: InputRequestContent) -> BoxFuture<'static, Result<String>>;
+}
+
+/// Read the input from the terminal, without echo for passwords.
+#[derive(Default, Debug, Clone)]
+pub struct StdinInputProvider;
+
+impl InputProvider for StdinInputProvider {
+    fn input(&self, request: InputRequestContent) -> BoxFuture<'static, Result<String>> {
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || read_input(&request))
+                .await
+                .map_err(|e| JupyterApiError::InputError(e.to_string()))?
+        })
+    }
+}
+
+fn read_input(request: &InputRequestContent) -> Result<String> {
+    if request.password {
+        return Ok(rpassword::prompt_password(&request.prompt)?);
+    }
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(request.prompt.as_bytes())?;
+    stdout.flush()?;
+
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -1,26 +1,88 @@
+mod comm;
+mod connection;
+mod control;
+mod debug;
+mod execution;
+mod input;
+mod output;
+mod shell;
 mod types;
+mod widget;
 use super::error::JupyterApiError;
-use futures::sink::SinkExt;
-use futures_util::StreamExt;
+use super::proxy::ProxyMode;
+use super::retry::RetryPolicy;
+use super::tls::TlsConfig;
+use super::transport::{connect_tcp, Transport};
+pub use comm::*;
+pub use connection::*;
+pub use debug::*;
+pub use execution::*;
+pub use input::*;
+pub use output::*;
+use reqwest::header::HeaderMap;
 use serde::Serialize;
-use std::pin::Pin;
-use tokio::time::{sleep, Duration, Sleep};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use std::fmt;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UnixStream;
+use tokio::time::{sleep, Duration};
+use tokio_tungstenite::{
+    client_async_tls_with_config, tungstenite::client::IntoClientRequest, MaybeTlsStream,
+    WebSocketStream,
+};
 pub use types::*;
 use url;
+pub use widget::*;
 
 type Result<T> = std::result::Result<T, JupyterApiError>;
 
+/// Tcp, proxy tunnel or unix socket stream under the websocket.
+pub trait KernelStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> KernelStream for T {}
+
+pub type KernelWebSocket = WebSocketStream<MaybeTlsStream<Box<dyn KernelStream>>>;
+
 pub struct KernelApiClient {
     url: String,
+    config: KernelClientConfig,
+}
+
+/// Settings of the websocket connection to the kernel.
+#[derive(Default, Debug, Clone)]
+pub struct KernelClientConfig {
+    pub tls: TlsConfig,
+    pub proxy: ProxyMode,
+    pub transport: Transport,
+    pub connect_timeout: Option<Duration>,
+    /// Default timeout to wait for the kernel messages. [`DEFAULT_TIMEOUT_SEC`] if `None`.
+    pub kernel_timeout: Option<Duration>,
+    /// Headers of the websocket handshake.
+    pub headers: HeaderMap,
+    /// `username` in the header of the kernel messages.
+    pub username: Option<String>,
+    pub retry_policy: RetryPolicy,
+    /// Answers the `input_request`s of the executions with `allow_stdin`.
+    /// The time waiting for an answer counts toward the timeout of the execution.
+    pub input_provider: Option<Arc<dyn InputProvider>>,
+}
+
+impl fmt::Debug for dyn InputProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InputProvider")
+    }
 }
 
-const DEFAULT_TIMEOUT_SEC: u64 = 120;
-const DEFAULT_WAIT_SUCCESSION_RESULT_MILLI_SEC: u64 = 100;
+pub const DEFAULT_TIMEOUT_SEC: u64 = 120;
 
+/// Execution state in the `status` message when the kernel has finished handling a request.
+const EXECUTION_STATE_IDLE: &str = "idle";
+
+#[allow(clippy::large_enum_variant)]
 pub enum WaitResultResponse {
+    /// Return the message without waiting for the rest.
     KernelResponse(KernelResponse),
-    WaitSuccession(Sleep),
+    /// All the messages have arrived. Return [`WaitResult::latest_result`].
+    Completed,
 }
 
 pub trait WaitResult {
@@ -28,49 +90,53 @@ pub trait WaitResult {
     fn latest_result(self) -> (Option<KernelResponse>, Option<Vec<KernelResponse>>);
 }
 
-struct WaitResultAndDisplayData {
+/// Whether the message is the `status: idle` that follows all the outputs of a request.
+pub fn is_idle_status(message: &KernelResponse) -> bool {
+    message.msg_type == MessageType::Status
+        && matches!(
+            message.as_status_content(),
+            Ok(Some(StatusContent { execution_state, .. })) if execution_state == EXECUTION_STATE_IDLE
+        )
+}
+
+/// Wait for the execute reply on shell and the idle status on iopub,
+/// collecting the result, the streams and the display data.
+#[derive(Default)]
+pub(crate) struct WaitResultAndDisplayData {
     inner_latest_result: Option<KernelResponse>,
     inner_stream_result: Vec<KernelResponse>,
+    reply_received: bool,
+    idle_received: bool,
 }
 
-impl Default for WaitResultAndDisplayData {
-    fn default() -> Self {
-        Self {
-            inner_latest_result: None,
-            inner_stream_result: vec![],
-        }
-    }
-}
 impl WaitResult for WaitResultAndDisplayData {
     fn check(&mut self, message: KernelResponse) -> Option<WaitResultResponse> {
         match message.msg_type {
-            MessageType::ExecuteResult => {
+            MessageType::ExecuteResult | MessageType::Error => {
                 self.inner_latest_result = Some(message);
-                Some(WaitResultResponse::WaitSuccession(sleep(
-                    Duration::from_millis(DEFAULT_WAIT_SUCCESSION_RESULT_MILLI_SEC),
-                )))
             }
 
-            MessageType::Stream => {
+            MessageType::Stream | MessageType::DisplayData => {
                 self.inner_stream_result.push(message);
-                Some(WaitResultResponse::WaitSuccession(sleep(
-                    Duration::from_millis(DEFAULT_WAIT_SUCCESSION_RESULT_MILLI_SEC),
-                )))
             }
 
             MessageType::ExecuteReply => {
                 if self.inner_latest_result.is_none() {
                     self.inner_latest_result = Some(message);
                 }
-                Some(WaitResultResponse::WaitSuccession(sleep(
-                    Duration::from_millis(DEFAULT_WAIT_SUCCESSION_RESULT_MILLI_SEC),
-                )))
+                self.reply_received = true;
             }
 
-            MessageType::DisplayData | MessageType::Error => {
-                Some(WaitResultResponse::KernelResponse(message))
+            MessageType::Status if is_idle_status(&message) => {
+                self.idle_received = true;
             }
-            _ => None,
+            _ => {}
+        }
+
+        if self.reply_received && self.idle_received {
+            Some(WaitResultResponse::Completed)
+        } else {
+            None
         }
     }
 
@@ -98,9 +164,23 @@ impl WaitResult for WaitResultAndDisplayData {
 
 impl KernelApiClient {
     pub fn new(url_without_protocol: &str, kernel_id: &str, secure: bool) -> Self {
+        Self::new_with_config(
+            url_without_protocol,
+            kernel_id,
+            secure,
+            KernelClientConfig::default(),
+        )
+    }
+
+    pub fn new_with_config(
+        url_without_protocol: &str,
+        kernel_id: &str,
+        secure: bool,
+        config: KernelClientConfig,
+    ) -> Self {
         let protocol = if secure { "wss" } else { "ws" };
         let url = format!("{protocol}://{url_without_protocol}/api/kernels/{kernel_id}/channels");
-        Self { url }
+        Self { url, config }
     }
 
     pub async fn run_code(
@@ -112,94 +192,136 @@ impl KernelApiClient {
             .await
     }
 
+    /// Answer the `input_request`s of the executions with `allow_stdin` by the provider.
+    pub fn input_provider<P: InputProvider + 'static>(mut self, provider: P) -> Self {
+        self.config.input_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Run the code over a new connection, yielding the outputs as they arrive.
+    pub async fn execute_stream(
+        &self,
+        request: impl Into<KernelCodeRequest>,
+    ) -> Result<ExecutionStream> {
+        self.connect().await?.execute_stream(request)
+    }
+
+    /// Open a websocket to the kernel that is kept open for multiple requests.
+    pub async fn connect(&self) -> Result<KernelConnection> {
+        let socket = self.connect_socket().await?;
+        Ok(KernelConnection::new(socket, self.config.clone()))
+    }
+
+    async fn connect_socket(&self) -> Result<KernelWebSocket> {
+        let mut retries = 0;
+        loop {
+            let result = match self.config.connect_timeout {
+                Some(connect_timeout) => {
+                    match tokio::time::timeout(connect_timeout, self.connect_socket_once()).await {
+                        Ok(result) => result,
+                        Err(_) => Err(JupyterApiError::ConnectTimeout),
+                    }
+                }
+                None => self.connect_socket_once().await,
+            };
+
+            match result {
+                Err(e) if e.is_retryable() && retries < self.config.retry_policy.max_retries => {
+                    retries += 1;
+                    let delay = self.config.retry_policy.backoff(retries);
+                    log::debug!("retry connecting to the kernel in {delay:?} ({retries}): {e}");
+                    sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+    async fn connect_socket_once(&self) -> Result<KernelWebSocket> {
+        let parsed_url = url::Url::parse(&self.url)?;
+
+        let stream: Box<dyn KernelStream> = match &self.config.transport {
+            Transport::Unix(socket_path) => Box::new(UnixStream::connect(socket_path).await?),
+            Transport::Tcp => match self.config.proxy.proxy_for(&parsed_url) {
+                Some(proxy) => {
+                    log::debug!("connect to the kernel via proxy {}", proxy.url);
+                    Box::new(proxy.connect_tunnel(&parsed_url).await?)
+                }
+                None => {
+                    let invalid_url =
+                        || JupyterApiError::InvalidJupyterBaseUrlError(self.url.clone());
+                    let host = parsed_url.host().ok_or_else(invalid_url)?;
+                    let port = parsed_url.port_or_known_default().ok_or_else(invalid_url)?;
+                    let stream = connect_tcp(host, port).await?;
+                    stream.set_nodelay(true)?;
+                    Box::new(stream)
+                }
+            },
+        };
+
+        let mut request = parsed_url.as_str().into_client_request()?;
+        request.headers_mut().extend(self.config.headers.clone());
+
+        let connector = self.config.tls.ws_connector()?;
+        let (connection, _resp) =
+            client_async_tls_with_config(request, stream, None, connector).await?;
+        Ok(connection)
+    }
+
+    /// Run the request over a new connection that is closed afterwards.
+    /// Use [`Self::connect`] to send many requests over one connection.
     pub async fn run_and_wait_message<F, Req: Serialize>(
         &self,
         request: Req,
-        mut wait_result: F,
+        wait_result: F,
         timeout: Option<Duration>,
     ) -> Result<CompositeKernelResponses>
     where
         F: WaitResult,
     {
-        let parsed_url = url::Url::parse(&self.url)?;
-        let (connection, _resp) = connect_async(&parsed_url).await?;
-        let (mut writer, mut reader) = connection.split();
+        self.connect()
+            .await?
+            .run_and_wait_message(request, wait_result, timeout)
+            .await
+    }
+}
 
-        writer
-            .send(Message::Ping("ping".as_bytes().to_vec()))
-            .await?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
 
-        writer
-            .send(Message::Text(serde_json::to_string(&request)?))
-            .await?;
-        let timeout = sleep(timeout.unwrap_or(Duration::from_secs(DEFAULT_TIMEOUT_SEC)));
-        let mut wait_succession_timeout = sleep(Duration::from_secs(u64::MAX));
-        tokio::pin!(timeout);
+    fn message(msg_type: &str, content: serde_json::Value) -> KernelResponse {
+        serde_json::from_value(json!({
+            "header": {"username": "u", "session": "s", "msg_type": msg_type, "version": "5.3", "msg_id": "m", "date": "d"},
+            "msg_id": "m",
+            "msg_type": msg_type,
+            "parent_header": {"msg_id": "req", "session": "s"},
+            "metadata": {},
+            "content": content,
+            "buffers": [],
+            "channel": "iopub"
+        }))
+        .unwrap()
+    }
 
-        let mut pinned_wait_succession_timeout =
-            unsafe { Pin::new_unchecked(&mut wait_succession_timeout) };
+    #[test]
+    fn complete_on_reply_and_idle() {
+        let mut wait_result = WaitResultAndDisplayData::default();
+        let busy = message("status", json!({"execution_state": "busy"}));
+        let stream = message("stream", json!({"name": "stdout", "text": "1"}));
+        let reply = message(
+            "execute_reply",
+            json!({"status": "ok", "execution_count": 1}),
+        );
+        let idle = message("status", json!({"execution_state": "idle"}));
 
-        loop {
-            tokio::select! {
-                 _ = &mut timeout =>{
-                    log::debug!("timeout.");
-                    return Err(JupyterApiError::KernelMessageTimeout)
-                 },
-
-                 _ = &mut pinned_wait_succession_timeout  =>{
-                    match wait_result.latest_result() {
-                        (None ,None)=> return Err(JupyterApiError::KernelResponseTimeout),
-                        (Some(latest_result) ,None)=>{
-                            return Ok(CompositeKernelResponses::SingleResponse(latest_result))
-                        }
-                        (None ,Some(stream))=>{
-                            return Ok(CompositeKernelResponses::MultipleResponse(stream))
-                        }
-
-                        (Some(latest_result) ,Some(mut stream))=>{
-                            stream.push(latest_result);
-                            return Ok(CompositeKernelResponses::MultipleResponse(stream))
-                        }
-                    }
-                 },
-
-                 Some(receipt_message) = reader.next() =>{
-                    log::debug!("receipt_message: {receipt_message:?}");
-                    match receipt_message{
-                        Ok(message) =>{
-                            let resp: KernelResponse = match message{
-                                Message::Pong(_body) =>{continue},
-                                Message::Text(message) =>{
-                                    serde_json::from_str(message.as_str())?
-                                },
-                                Message::Close(_) => return Err(JupyterApiError::KernelConnectionClosed),
-                                Message::Ping(body) =>{
-                                    writer.send(Message::Pong(body)).await.ok();
-                                    continue
-                                },
-                                _ => {
-                                    continue
-                                }
-                            };
-
-                            if let Some(checked_result) = wait_result.check(resp){
-                                match checked_result{
-                                     WaitResultResponse::KernelResponse(response)=>return Ok(CompositeKernelResponses::SingleResponse(response)),
-                                     WaitResultResponse::WaitSuccession(succession_timeout)=>{
-                                        wait_succession_timeout = succession_timeout;
-                                        pinned_wait_succession_timeout =
-                                            unsafe { Pin::new_unchecked(&mut wait_succession_timeout) };
-                                     }
-                                }
-
-                            }
-                        },
-                        Err(e) =>{
-                          return Err(JupyterApiError::KernelMessageError(format!("{e}")))
-                        }
-                    }
-                 },
-            }
-        }
+        assert!(wait_result.check(busy).is_none());
+        assert!(wait_result.check(stream.clone()).is_none());
+        assert!(wait_result.check(reply).is_none());
+        assert!(matches!(
+            wait_result.check(idle),
+            Some(WaitResultResponse::Completed)
+        ));
+        assert_eq!(wait_result.latest_result(), (Some(stream), None));
     }
 }
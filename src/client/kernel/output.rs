@@ -0,0 +1,257 @@
+use super::execution::ExecutionEvent;
+use super::types::*;
+use std::collections::HashMap;
+
+/// An output of an execution as a notebook renders it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderedOutput {
+    /// The consecutive texts of a stream, with the carriage returns applied.
+    Stream {
+        name: String,
+        text: String,
+    },
+    DisplayData(DisplayDataContent),
+    ExecuteResult(ExecuteResultContent),
+    Error(ErrorContent),
+}
+
+/// The outputs of an execution with `clear_output` and `update_display_data` applied.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct OutputArea {
+    outputs: Vec<RenderedOutput>,
+    /// Indexes of the outputs by `display_id`.
+    displays: HashMap<String, Vec<usize>>,
+    /// `clear_output` with `wait` is pending until the next output.
+    clear_on_next_output: bool,
+    /// The unterminated last line of the last output with the carriage returns applied,
+    /// if the output is a stream.
+    stream_line: String,
+    /// Chars of `stream_line` before the position that the next text overwrites, i.e. 0 after a `\r`.
+    stream_column: usize,
+    /// Where `stream_line` starts in the text of the last output.
+    stream_line_start: usize,
+}
+
+impl OutputArea {
+    pub fn outputs(&self) -> &[RenderedOutput] {
+        &self.outputs
+    }
+
+    pub fn into_outputs(self) -> Vec<RenderedOutput> {
+        self.outputs
+    }
+
+    pub fn apply(&mut self, event: &ExecutionEvent) {
+        match event {
+            ExecutionEvent::Stream(stream) => {
+                self.before_output();
+                let continued = matches!(self.outputs.last(),
+                    Some(RenderedOutput::Stream { name, .. }) if *name == stream.name);
+                if !continued {
+                    self.outputs.push(RenderedOutput::Stream {
+                        name: stream.name.clone(),
+                        text: String::new(),
+                    });
+                    self.stream_line.clear();
+                    self.stream_column = 0;
+                    self.stream_line_start = 0;
+                }
+                self.append_stream(&stream.text);
+            }
+            ExecutionEvent::DisplayData(display) => {
+                self.before_output();
+                if let Some(display_id) = display.display_id() {
+                    self.displays
+                        .entry(display_id.to_string())
+                        .or_default()
+                        .push(self.outputs.len());
+                }
+                self.outputs
+                    .push(RenderedOutput::DisplayData(display.clone()));
+            }
+            ExecutionEvent::UpdateDisplayData(update) => {
+                let indexes = update
+                    .display_id()
+                    .and_then(|display_id| self.displays.get(display_id));
+                for index in indexes.into_iter().flatten() {
+                    if let Some(RenderedOutput::DisplayData(display)) = self.outputs.get_mut(*index)
+                    {
+                        display.data = update.data.clone();
+                        display.metadata = update.metadata.clone();
+                    }
+                }
+            }
+            ExecutionEvent::ExecuteResult(result) => {
+                self.before_output();
+                self.outputs
+                    .push(RenderedOutput::ExecuteResult(result.clone()));
+            }
+            ExecutionEvent::Error(error) => {
+                self.before_output();
+                self.outputs.push(RenderedOutput::Error(error.clone()));
+            }
+            ExecutionEvent::ClearOutput(clear) => {
+                if clear.wait {
+                    self.clear_on_next_output = true;
+                } else {
+                    self.clear();
+                }
+            }
+            ExecutionEvent::Status(_) | ExecutionEvent::Reply(_) => {}
+        }
+    }
+
+    /// Render the completed lines once, and keep only the last line to re-render.
+    fn append_stream(&mut self, chunk: &str) {
+        let text = match self.outputs.last_mut() {
+            Some(RenderedOutput::Stream { text, .. }) => text,
+            _ => return,
+        };
+        for (i, piece) in chunk.split('\n').enumerate() {
+            if i > 0 {
+                text.truncate(self.stream_line_start);
+                text.push_str(&self.stream_line);
+                text.push('\n');
+                self.stream_line_start = text.len();
+                self.stream_line.clear();
+                self.stream_column = 0;
+            }
+            overwrite(&mut self.stream_line, &mut self.stream_column, piece);
+        }
+        text.truncate(self.stream_line_start);
+        text.push_str(&self.stream_line);
+    }
+
+    fn before_output(&mut self) {
+        if self.clear_on_next_output {
+            self.clear();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.outputs.clear();
+        self.displays.clear();
+        self.clear_on_next_output = false;
+        self.stream_line.clear();
+        self.stream_column = 0;
+        self.stream_line_start = 0;
+    }
+}
+
+/// Write `text` over the line from the `column`th char, going back to the start at each `\r`,
+/// as a terminal and JupyterLab show it, e.g. `"abcdef\rxy"` is `"xycdef"`.
+fn overwrite(line: &mut String, column: &mut usize, text: &str) {
+    let byte_index = |line: &str, column: usize| {
+        line.char_indices()
+            .nth(column)
+            .map_or(line.len(), |(i, _)| i)
+    };
+    for (i, segment) in text.split('\r').enumerate() {
+        if i > 0 {
+            *column = 0;
+        }
+        let width = segment.chars().count();
+        let start = byte_index(line, *column);
+        let end = byte_index(line, *column + width);
+        line.replace_range(start..end, segment);
+        *column += width;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::types::{Data, MIME_TEXT_PLAIN};
+    use super::*;
+    use serde_json::json;
+
+    fn stream(text: &str) -> ExecutionEvent {
+        ExecutionEvent::Stream(StreamContent {
+            name: "stdout".to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    fn display(display_id: &str, text: &str) -> DisplayDataContent {
+        DisplayDataContent {
+            data: Data::from_iter([(MIME_TEXT_PLAIN, json!(text))]),
+            transient: Transient {
+                display_id: Some(display_id.to_string()),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_outputs() {
+        let mut area = OutputArea::default();
+        area.apply(&stream(" 10%|#   |\r"));
+        assert!(
+            matches!(&area.outputs()[0], RenderedOutput::Stream { text, .. } if text == " 10%|#   |")
+        );
+        area.apply(&stream(" 50%|#####|\r100%|##########|\n"));
+        assert_eq!(area.stream_line, "");
+        area.apply(&ExecutionEvent::DisplayData(display("plot", "v1")));
+        area.apply(&ExecutionEvent::UpdateDisplayData(display("plot", "v2")));
+        assert_eq!(
+            area.outputs(),
+            &[
+                RenderedOutput::Stream {
+                    name: "stdout".to_string(),
+                    text: "100%|##########|\n".to_string()
+                },
+                RenderedOutput::DisplayData(display("plot", "v2")),
+            ]
+        );
+
+        area.apply(&ExecutionEvent::ClearOutput(ClearOutputContent {
+            wait: true,
+        }));
+        assert_eq!(area.outputs().len(), 2);
+        area.apply(&stream("done"));
+        assert_eq!(
+            area.into_outputs(),
+            vec![RenderedOutput::Stream {
+                name: "stdout".to_string(),
+                text: "done".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn render_progress_in_bounded_memory() {
+        let mut area = OutputArea::default();
+        area.apply(&stream("start\n"));
+        for i in 0..1000 {
+            area.apply(&stream(&format!("\r{i:>4}/1000")));
+            assert!(area.stream_line.len() <= "1000/1000".len());
+        }
+        // a shorter text overwrites only the start of the line
+        area.apply(&stream("\rdone\nnext"));
+        assert_eq!(
+            area.outputs(),
+            &[RenderedOutput::Stream {
+                name: "stdout".to_string(),
+                text: "start\ndone/1000\nnext".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn overwrite_with_carriage_returns() {
+        let render = |chunks: &[&str]| {
+            let mut area = OutputArea::default();
+            for chunk in chunks {
+                area.apply(&stream(chunk));
+            }
+            match area.into_outputs().pop() {
+                Some(RenderedOutput::Stream { text, .. }) => text,
+                output => panic!("unexpected {output:?}"),
+            }
+        };
+        assert_eq!(render(&["abcdef\rxy"]), "xycdef");
+        assert_eq!(render(&["abcdef\r", "xy"]), "xycdef");
+        assert_eq!(render(&["abcdef\r\n", "xy"]), "abcdef\nxy");
+        assert_eq!(render(&["ab\rx", "yz"]), "xyz");
+        assert_eq!(render(&["héllo\rhi"]), "hillo");
+    }
+}
@@ -0,0 +1,240 @@
+use super::connection::KernelConnection;
+use super::types::*;
+use super::{is_idle_status, KernelApiClient, Result, WaitResult, WaitResultResponse};
+use crate::client::error::JupyterApiError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::{sleep, Duration, Instant};
+
+const READY_PROBE_TIMEOUT_SEC: u64 = 5;
+const READY_PROBE_INTERVAL_MILLI_SEC: u64 = 200;
+
+/// Wait for the reply, and the idle status on iopub if `wait_idle`.
+struct WaitReply {
+    reply_type: MessageType,
+    reply: Option<KernelResponse>,
+    wait_idle: bool,
+    idle_received: bool,
+}
+
+impl WaitResult for WaitReply {
+    fn check(&mut self, message: KernelResponse) -> Option<WaitResultResponse> {
+        if message.msg_type == self.reply_type {
+            self.reply = Some(message);
+        } else if is_idle_status(&message) {
+            self.idle_received = true;
+        }
+
+        if self.reply.is_some() && (self.idle_received || !self.wait_idle) {
+            Some(WaitResultResponse::Completed)
+        } else {
+            None
+        }
+    }
+
+    fn latest_result(self) -> (Option<KernelResponse>, Option<Vec<KernelResponse>>) {
+        (self.reply, None)
+    }
+}
+
+impl KernelConnection {
+    /// Send a shell request and parse the content of its reply.
+    pub async fn request<C: Serialize, R: DeserializeOwned>(
+        &self,
+        request_type: MessageType,
+        reply_type: MessageType,
+        content: C,
+        timeout: Option<Duration>,
+    ) -> Result<R> {
+        self.wait_reply(
+            KernelRequest::new(request_type, content),
+            reply_type,
+            true,
+            timeout,
+        )
+        .await
+    }
+
+    /// Send the request and parse the content of its reply.
+    /// Waits for the idle status as well if `wait_idle`, so that no message of the request is left.
+    pub(super) async fn wait_reply<C: Serialize, R: DeserializeOwned>(
+        &self,
+        request: KernelRequest<C>,
+        reply_type: MessageType,
+        wait_idle: bool,
+        timeout: Option<Duration>,
+    ) -> Result<R> {
+        let wait_reply = WaitReply {
+            reply_type,
+            reply: None,
+            wait_idle,
+            idle_received: false,
+        };
+        let resp = match self
+            .run_and_wait_message(request, wait_reply, timeout)
+            .await?
+        {
+            CompositeKernelResponses::SingleResponse(resp) => resp,
+            CompositeKernelResponses::MultipleResponse(_) => {
+                return Err(JupyterApiError::EmptyResponse)
+            }
+        };
+
+        let content = resp.content.ok_or(JupyterApiError::EmptyResponse)?;
+        if content.get("status").and_then(|v| v.as_str()) == Some("error") {
+            let error: ErrorContent = serde_json::from_value(content)?;
+            return Err(JupyterApiError::KernelMessageError(format!(
+                "{}: {}",
+                error.ename, error.evalue
+            )));
+        }
+        Ok(serde_json::from_value(content)?)
+    }
+
+    /// Completions of the code at `cursor_pos`. (in unicode code points)
+    pub async fn complete(&self, code: &str, cursor_pos: usize) -> Result<CompleteReply> {
+        let content = CompleteRequestContent {
+            code: code.to_string(),
+            cursor_pos,
+        };
+        self.request(
+            MessageType::CompleteRequest,
+            MessageType::CompleteReply,
+            content,
+            None,
+        )
+        .await
+    }
+
+    /// Documentation of the object at `cursor_pos`. `detail_level` is 0 or 1.
+    pub async fn inspect(
+        &self,
+        code: &str,
+        cursor_pos: usize,
+        detail_level: u8,
+    ) -> Result<InspectReply> {
+        let content = InspectRequestContent {
+            code: code.to_string(),
+            cursor_pos,
+            detail_level,
+        };
+        self.request(
+            MessageType::InspectRequest,
+            MessageType::InspectReply,
+            content,
+            None,
+        )
+        .await
+    }
+
+    pub async fn is_complete(&self, code: &str) -> Result<IsCompleteReply> {
+        let content = IsCompleteRequestContent {
+            code: code.to_string(),
+        };
+        self.request(
+            MessageType::IsCompleteRequest,
+            MessageType::IsCompleteReply,
+            content,
+            None,
+        )
+        .await
+    }
+
+    /// Input history of the kernel, with the outputs if `output`.
+    pub async fn history(&self, access: HistoryAccess, output: bool) -> Result<Vec<HistoryEntry>> {
+        let content = HistoryRequestContent {
+            output,
+            raw: true,
+            access,
+        };
+        let reply: HistoryReply = self
+            .request(
+                MessageType::HistoryRequest,
+                MessageType::HistoryReply,
+                content,
+                None,
+            )
+            .await?;
+        Ok(reply.history)
+    }
+
+    pub async fn kernel_info(&self) -> Result<KernelInfoReply> {
+        self.kernel_info_with_timeout(None).await
+    }
+
+    async fn kernel_info_with_timeout(&self, timeout: Option<Duration>) -> Result<KernelInfoReply> {
+        self.request(
+            MessageType::KernelInfoRequest,
+            MessageType::KernelInfoReply,
+            KernelInfoRequestContent::default(),
+            timeout,
+        )
+        .await
+    }
+}
+
+impl KernelApiClient {
+    pub async fn complete(&self, code: &str, cursor_pos: usize) -> Result<CompleteReply> {
+        self.connect().await?.complete(code, cursor_pos).await
+    }
+
+    pub async fn inspect(
+        &self,
+        code: &str,
+        cursor_pos: usize,
+        detail_level: u8,
+    ) -> Result<InspectReply> {
+        self.connect()
+            .await?
+            .inspect(code, cursor_pos, detail_level)
+            .await
+    }
+
+    pub async fn is_complete(&self, code: &str) -> Result<IsCompleteReply> {
+        self.connect().await?.is_complete(code).await
+    }
+
+    pub async fn history(&self, access: HistoryAccess, output: bool) -> Result<Vec<HistoryEntry>> {
+        self.connect().await?.history(access, output).await
+    }
+
+    pub async fn kernel_info(&self) -> Result<KernelInfoReply> {
+        self.connect().await?.kernel_info().await
+    }
+
+    /// Wait until the kernel replies to `kernel_info_request`, e.g. after starting it.
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<KernelInfoReply> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(JupyterApiError::KernelMessageTimeout);
+            }
+            let probe_timeout = remaining.min(Duration::from_secs(READY_PROBE_TIMEOUT_SEC));
+
+            let result = match self.connect().await {
+                Ok(connection) => {
+                    connection
+                        .kernel_info_with_timeout(Some(probe_timeout))
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e) if is_not_ready(&e) => {
+                    log::debug!("kernel is not ready: {e}");
+                    sleep(remaining.min(Duration::from_millis(READY_PROBE_INTERVAL_MILLI_SEC)))
+                        .await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_not_ready(e: &JupyterApiError) -> bool {
+    matches!(
+        e,
+        JupyterApiError::KernelMessageTimeout | JupyterApiError::KernelConnectionClosed
+    ) || e.is_retryable()
+}
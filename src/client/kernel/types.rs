@@ -1,14 +1,44 @@
-use super::super::types::Data;
+use super::super::types::{Data, LanguageInfo};
 use super::super::JupyterApiError;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use serde_json::{error::Error as JsonError, Map as JMap, Value};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+/// `username` in the header of the kernel messages unless configured.
+pub const DEFAULT_USERNAME: &str = "jupyter-client-rs";
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MessageType {
     Stream,
     KernelInfoRequest,
+    CompleteRequest,
+    CompleteReply,
+    InspectRequest,
+    InspectReply,
+    IsCompleteRequest,
+    IsCompleteReply,
+    HistoryRequest,
+    HistoryReply,
+    KernelInfoReply,
+    CommOpen,
+    CommMsg,
+    CommClose,
+    CommInfoRequest,
+    CommInfoReply,
+    InputRequest,
+    InputReply,
+    InterruptRequest,
+    InterruptReply,
+    ShutdownRequest,
+    ShutdownReply,
+    DebugRequest,
+    DebugReply,
+    DebugEvent,
+    ClearOutput,
+    UpdateDisplayData,
     DisplayData,
     ExecuteRequest,
     ExecuteInput,
@@ -32,10 +62,19 @@ pub struct KernelRequestHeader {
     version: String,
 }
 
+impl KernelRequestHeader {
+    pub fn new(message_type: MessageType) -> Self {
+        Self {
+            message_type,
+            ..Default::default()
+        }
+    }
+}
+
 impl Default for KernelRequestHeader {
     fn default() -> Self {
         KernelRequestHeader {
-            username: Some("jupyter-client-rs".to_string()),
+            username: Some(DEFAULT_USERNAME.to_string()),
             session_id: Some(Uuid::new_v4().to_string()),
             message_id: Some(Uuid::new_v4().to_string()),
             message_type: MessageType::ExecuteRequest,
@@ -50,35 +89,144 @@ impl Default for KernelRequestHeader {
 pub struct KernelCodeContent {
     code: String,
     silent: bool,
+    store_history: bool,
+    user_expressions: HashMap<String, String>,
+    allow_stdin: bool,
+    stop_on_error: bool,
 }
 impl From<String> for KernelCodeContent {
     fn from(code: String) -> Self {
         Self {
             code,
             silent: false,
+            store_history: true,
+            user_expressions: HashMap::new(),
+            allow_stdin: false,
+            stop_on_error: true,
+        }
+    }
+}
+
+impl From<&str> for KernelCodeContent {
+    fn from(code: &str) -> Self {
+        code.to_string().into()
+    }
+}
+
+impl KernelCodeContent {
+    /// Run without broadcasting the outputs, and without storing the history. (implies `store_history(false)`)
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        if silent {
+            self.store_history = false;
         }
+        self
+    }
+
+    /// Whether to add the code to the history and increment the execution counter.
+    pub fn store_history(mut self, store_history: bool) -> Self {
+        self.store_history = store_history;
+        self
+    }
+
+    /// Expression evaluated after the code. The result is in [`ExecuteReplyContent::user_expressions`] by `name`.
+    pub fn user_expression(mut self, name: &str, expression: &str) -> Self {
+        self.user_expressions
+            .insert(name.to_string(), expression.to_string());
+        self
+    }
+
+    /// Whether the kernel may request input from the client.
+    /// Requires an [`super::InputProvider`] in the [`super::KernelClientConfig`].
+    /// Without it the kernel raises an error as soon as the code asks for input.
+    pub fn allow_stdin(mut self, allow_stdin: bool) -> Self {
+        self.allow_stdin = allow_stdin;
+        self
+    }
+
+    pub fn allows_stdin(&self) -> bool {
+        self.allow_stdin
+    }
+
+    /// Whether to abort the queued executions on an error.
+    pub fn stop_on_error(mut self, stop_on_error: bool) -> Self {
+        self.stop_on_error = stop_on_error;
+        self
     }
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct KernelCodeRequest {
+pub struct KernelRequest<C> {
     header: KernelRequestHeader,
-    parent_header: KernelRequestHeader,
+    parent_header: Value,
     metadata: Value,
-    content: KernelCodeContent,
+    content: C,
+    channel: Channel,
 }
 
-impl From<String> for KernelCodeRequest {
-    fn from(code: String) -> Self {
-        let header = KernelRequestHeader::default();
+impl<C> KernelRequest<C> {
+    /// A request on the shell channel.
+    pub fn new(message_type: MessageType, content: C) -> Self {
+        let header = KernelRequestHeader::new(message_type);
         Self {
-            parent_header: header.clone(),
+            parent_header: serde_json::to_value(&header).unwrap_or_default(),
             header,
             metadata: Value::Object(JMap::new()),
-            content: KernelCodeContent::from(code),
+            content,
+            channel: Channel::Shell,
+        }
+    }
+
+    /// A reply to the request from the kernel, e.g. `input_reply` to `input_request`.
+    pub fn reply_to(
+        message_type: MessageType,
+        channel: Channel,
+        parent: &Header,
+        content: C,
+    ) -> Self {
+        Self {
+            header: KernelRequestHeader::new(message_type),
+            parent_header: serde_json::to_value(parent).unwrap_or_default(),
+            metadata: Value::Object(JMap::new()),
+            content,
+            channel,
         }
     }
+
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn content(&self) -> &C {
+        &self.content
+    }
+}
+
+/// Channel of the kernel that a message is sent over.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    Shell,
+    Iopub,
+    Stdin,
+    Control,
+}
+
+pub type KernelCodeRequest = KernelRequest<KernelCodeContent>;
+
+impl From<KernelCodeContent> for KernelCodeRequest {
+    fn from(content: KernelCodeContent) -> Self {
+        Self::new(MessageType::ExecuteRequest, content)
+    }
+}
+
+impl From<String> for KernelCodeRequest {
+    fn from(code: String) -> Self {
+        KernelCodeContent::from(code).into()
+    }
 }
 
 impl From<&str> for KernelCodeRequest {
@@ -87,6 +235,7 @@ impl From<&str> for KernelCodeRequest {
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CompositeKernelResponses {
     SingleResponse(KernelResponse),
@@ -101,7 +250,9 @@ pub struct KernelResponse {
     pub parent_header: ParentHeader,
     pub metadata: Metadata,
     pub content: Option<Value>,
-    pub buffers: Vec<Value>,
+    /// Binary buffers of the message, e.g. numpy arrays of widgets.
+    #[serde(skip)]
+    pub buffers: Vec<Bytes>,
     pub channel: String,
 }
 
@@ -110,33 +261,33 @@ impl KernelResponse {
         match &self.msg_type {
             MessageType::ExecuteInput => {
                 let r = self.as_execute_input_content()?;
-                Ok(r.map(|v| KernelContent::ExecuteInputContent(v)))
+                Ok(r.map(KernelContent::ExecuteInputContent))
             }
             MessageType::ExecuteReply => {
                 let r = self.as_execute_reply_content()?;
-                Ok(r.map(|v| KernelContent::ExecuteReplyContent(v)))
+                Ok(r.map(KernelContent::ExecuteReplyContent))
             }
             MessageType::ExecuteResult => {
                 let r = self.as_execute_result_content()?;
-                Ok(r.map(|v| KernelContent::ExecuteResultContent(v)))
+                Ok(r.map(KernelContent::ExecuteResultContent))
             }
             MessageType::DisplayData => {
                 let r = self.as_display_data_content()?;
-                Ok(r.map(|v| KernelContent::DisplayData(v)))
+                Ok(r.map(KernelContent::DisplayData))
             }
 
             MessageType::Error => {
                 let r = self.as_error_content()?;
-                Ok(r.map(|v| KernelContent::ErrorContent(v)))
+                Ok(r.map(KernelContent::ErrorContent))
             }
             MessageType::Status => {
                 let r = self.as_status_content()?;
-                Ok(r.map(|v| KernelContent::StatusContent(v)))
+                Ok(r.map(KernelContent::StatusContent))
             }
 
             MessageType::Stream => {
                 let r = self.as_stream_content()?;
-                Ok(r.map(|v| KernelContent::StreamContent(v)))
+                Ok(r.map(KernelContent::StreamContent))
             }
 
             typ => Err(JupyterApiError::InvalidMessageType(format!("{:?}", typ))),
@@ -191,6 +342,35 @@ impl KernelResponse {
             None => Ok(None),
         }
     }
+
+    pub fn as_input_request_content(&self) -> Result<Option<InputRequestContent>, JsonError> {
+        match self.content.clone() {
+            Some(content) => serde_json::from_value(content),
+            None => Ok(None),
+        }
+    }
+
+    pub fn as_clear_output_content(&self) -> Result<Option<ClearOutputContent>, JsonError> {
+        match self.content.clone() {
+            Some(content) => serde_json::from_value(content),
+            None => Ok(None),
+        }
+    }
+
+    pub fn as_comm_open_content(&self) -> Result<Option<CommOpenContent>, JsonError> {
+        match self.content.clone() {
+            Some(content) => serde_json::from_value(content),
+            None => Ok(None),
+        }
+    }
+
+    /// Content of `comm_msg` and `comm_close`.
+    pub fn as_comm_msg_content(&self) -> Result<Option<CommMsgContent>, JsonError> {
+        match self.content.clone() {
+            Some(content) => serde_json::from_value(content),
+            None => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -203,12 +383,24 @@ pub struct Header {
     pub date: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Header of the request that caused the message.
+/// Empty (`{}`) for the messages not caused by a request, e.g. `status: starting`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ParentHeader {
+    pub msg_id: Option<String>,
     pub username: String,
     pub session: String,
-    pub msg_type: MessageType,
+    pub msg_type: Option<MessageType>,
     pub version: String,
+    pub date: Option<String>,
+}
+
+impl ParentHeader {
+    /// Whether the message is a reply to the request of the `msg_id` in the `session`.
+    pub fn is_reply_to(&self, msg_id: &str, session: &str) -> bool {
+        self.msg_id.as_deref() == Some(msg_id) && self.session == session
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -225,7 +417,27 @@ pub struct ExecuteInputContent {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ExecuteReplyContent {
     pub status: String,
-    pub execution_count: i64,
+    /// Not sent with an `aborted` reply.
+    #[serde(default)]
+    pub execution_count: Option<i64>,
+    /// Results of [`KernelCodeContent::user_expression`] by name.
+    #[serde(default)]
+    pub user_expressions: HashMap<String, UserExpressionResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UserExpressionResult {
+    Ok {
+        data: Data,
+        #[serde(default)]
+        metadata: Value,
+    },
+    Error {
+        ename: String,
+        evalue: String,
+        traceback: Vec<String>,
+    },
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -234,17 +446,43 @@ pub struct ExecuteResultContent {
     pub data: Data,
 }
 
+/// Content of `display_data` and `update_display_data`.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DisplayDataContent {
     pub data: Data,
+    #[serde(default)]
     pub metadata: DisplayDataMetadata,
+    #[serde(default)]
+    pub transient: Transient,
+}
+
+impl DisplayDataContent {
+    pub fn display_id(&self) -> Option<&str> {
+        self.transient.display_id.as_deref()
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DisplayDataMetadata {
     needs_background: String,
 }
 
+/// Fields of a display that are not part of the output.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transient {
+    /// Id to update the display later with `update_display_data`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_id: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClearOutputContent {
+    /// Clear when the next output arrives instead of right now.
+    #[serde(default)]
+    pub wait: bool,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StatusContent {
     pub execution_state: String,
@@ -263,6 +501,235 @@ pub struct ErrorContent {
     pub traceback: Vec<String>,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompleteRequestContent {
+    pub code: String,
+    pub cursor_pos: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompleteReply {
+    pub matches: Vec<String>,
+    pub cursor_start: usize,
+    pub cursor_end: usize,
+    #[serde(default)]
+    pub metadata: CompleteMetadata,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompleteMetadata {
+    /// Type information of the matches, if the kernel provides it.
+    #[serde(rename = "_jupyter_types_experimental", default)]
+    pub types: Vec<CompletionType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionType {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    #[serde(rename = "type")]
+    pub completion_type: Option<String>,
+    pub signature: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct InspectRequestContent {
+    pub code: String,
+    pub cursor_pos: usize,
+    /// 0 for the docs, 1 for more detail such as the source.
+    pub detail_level: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InspectReply {
+    /// Whether an object was found at the cursor.
+    pub found: bool,
+    #[serde(default)]
+    pub data: Data,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct IsCompleteRequestContent {
+    pub code: String,
+}
+
+/// Whether the code is ready to be executed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IsCompleteReply {
+    Complete,
+    /// `indent` is the suggested indentation of the next line.
+    Incomplete {
+        #[serde(default)]
+        indent: String,
+    },
+    Invalid,
+    Unknown,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRequestContent {
+    /// Whether to include the outputs.
+    pub output: bool,
+    /// Whether to return the raw input instead of the transformed one.
+    pub raw: bool,
+    #[serde(flatten)]
+    pub access: HistoryAccess,
+}
+
+/// Which history entries to fetch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "hist_access_type", rename_all = "snake_case")]
+pub enum HistoryAccess {
+    /// The last `n` entries.
+    Tail { n: usize },
+    /// Lines `start..stop` of the session. A negative `session` counts back from the current one (0).
+    Range { session: i64, start: i64, stop: i64 },
+    /// Entries matching the glob `pattern`.
+    Search {
+        pattern: String,
+        n: usize,
+        unique: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HistoryReply {
+    pub history: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "RawHistoryEntry")]
+pub struct HistoryEntry {
+    pub session: i64,
+    pub line: i64,
+    pub input: String,
+    /// Only with `output`, and `None` if the line had no output.
+    pub output: Option<String>,
+}
+
+/// `[session, line, input]` or `[session, line, [input, output]]`
+#[derive(Deserialize)]
+struct RawHistoryEntry(i64, i64, RawHistoryInput);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawHistoryInput {
+    Input(String),
+    InputOutput(String, Option<String>),
+}
+
+impl From<RawHistoryEntry> for HistoryEntry {
+    fn from(RawHistoryEntry(session, line, input): RawHistoryEntry) -> Self {
+        let (input, output) = match input {
+            RawHistoryInput::Input(input) => (input, None),
+            RawHistoryInput::InputOutput(input, output) => (input, output),
+        };
+        Self {
+            session,
+            line,
+            input,
+            output,
+        }
+    }
+}
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KernelInfoRequestContent {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KernelInfoReply {
+    pub protocol_version: String,
+    pub implementation: String,
+    pub implementation_version: String,
+    pub language_info: LanguageInfo,
+    #[serde(default)]
+    pub banner: String,
+    #[serde(default)]
+    pub help_links: Vec<HelpLink>,
+    /// Whether the kernel supports the debugger.
+    #[serde(default)]
+    pub debugger: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelpLink {
+    pub text: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommOpenContent {
+    pub comm_id: String,
+    pub target_name: String,
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_module: Option<String>,
+}
+
+/// Content of `comm_msg` and `comm_close`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommMsgContent {
+    pub comm_id: String,
+    #[serde(default)]
+    pub data: Value,
+}
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommInfoRequestContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommInfoReply {
+    /// Keyed by `comm_id`.
+    pub comms: HashMap<String, CommInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommInfo {
+    pub target_name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRequestContent {
+    pub prompt: String,
+    /// Whether the input should not be echoed, e.g. `getpass`.
+    #[serde(default)]
+    pub password: bool,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputReplyContent {
+    pub value: String,
+}
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterruptRequestContent {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterruptReply {
+    pub status: String,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownRequestContent {
+    pub restart: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownReply {
+    pub status: String,
+    /// Whether the kernel is restarting.
+    #[serde(default)]
+    pub restart: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum KernelContent {
     DisplayData(DisplayDataContent),
@@ -273,3 +740,165 @@ pub enum KernelContent {
     ExecuteReplyContent(ExecuteReplyContent),
     ExecuteInputContent(ExecuteInputContent),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn execute_request_options() {
+        let request: KernelCodeRequest = KernelCodeContent::from("x = 1")
+            .store_history(false)
+            .user_expression("double", "x * 2")
+            .into();
+        let content = serde_json::to_value(&request).unwrap()["content"].clone();
+        assert_eq!(
+            content,
+            json!({
+                "code": "x = 1",
+                "silent": false,
+                "store_history": false,
+                "user_expressions": {"double": "x * 2"},
+                "allow_stdin": false,
+                "stop_on_error": true,
+            })
+        );
+
+        let reply: ExecuteReplyContent = serde_json::from_value(json!({
+            "status": "ok",
+            "execution_count": 1,
+            "user_expressions": {
+                "double": {"status": "ok", "data": {"text/plain": "2"}, "metadata": {}},
+            },
+        }))
+        .unwrap();
+        assert!(matches!(
+            &reply.user_expressions["double"],
+            UserExpressionResult::Ok { data, .. } if data.text_plain().as_deref() == Some("2")
+        ));
+
+        // requests after an error with `stop_on_error` are aborted without a count
+        let reply: ExecuteReplyContent =
+            serde_json::from_value(json!({"status": "aborted"})).unwrap();
+        assert_eq!(reply.status, "aborted");
+        assert_eq!(reply.execution_count, None);
+        assert!(reply.user_expressions.is_empty());
+    }
+
+    #[test]
+    fn is_complete_reply() {
+        let reply: IsCompleteReply =
+            serde_json::from_value(json!({"status": "incomplete", "indent": "    "})).unwrap();
+        assert_eq!(
+            reply,
+            IsCompleteReply::Incomplete {
+                indent: "    ".to_string()
+            }
+        );
+        let reply: IsCompleteReply =
+            serde_json::from_value(json!({"status": "complete", "indent": ""})).unwrap();
+        assert_eq!(reply, IsCompleteReply::Complete);
+    }
+
+    #[test]
+    fn history() {
+        let content = HistoryRequestContent {
+            output: true,
+            raw: true,
+            access: HistoryAccess::Tail { n: 10 },
+        };
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!({"output": true, "raw": true, "hist_access_type": "tail", "n": 10})
+        );
+
+        let reply: HistoryReply = serde_json::from_value(json!({
+            "status": "ok",
+            "history": [[1, 1, "x = 1"], [1, 2, ["x", "1"]], [1, 3, ["print(x)", null]]],
+        }))
+        .unwrap();
+        assert_eq!(reply.history[0].input, "x = 1");
+        assert_eq!(reply.history[0].output, None);
+        assert_eq!(reply.history[1].output.as_deref(), Some("1"));
+        assert_eq!(reply.history[2].line, 3);
+    }
+
+    #[test]
+    fn kernel_info_reply() {
+        let reply: KernelInfoReply = serde_json::from_value(json!({
+            "status": "ok",
+            "protocol_version": "5.3",
+            "implementation": "ipython",
+            "implementation_version": "8.4.0",
+            "language_info": {
+                "name": "python",
+                "version": "3.10.4",
+                "mimetype": "text/x-python",
+                "codemirror_mode": {"name": "ipython", "version": 3},
+                "pygments_lexer": "ipython3",
+                "nbconvert_exporter": "python",
+                "file_extension": ".py"
+            },
+            "banner": "Python 3.10.4",
+            "help_links": [{"text": "Python Reference", "url": "https://docs.python.org/3.10"}]
+        }))
+        .unwrap();
+        assert_eq!(reply.language_info.name, "python");
+        assert_eq!(reply.language_info.codemirror_mode_name(), Some("ipython"));
+        assert_eq!(reply.language_info.pygment_lexer, "ipython3");
+        assert_eq!(reply.help_links.len(), 1);
+        assert!(!reply.debugger);
+    }
+
+    #[test]
+    fn inspect_reply() {
+        let reply: InspectReply = serde_json::from_value(json!({
+            "status": "ok",
+            "found": true,
+            "data": {"text/plain": "Signature: print(*args, sep=' ', end='\\n')"},
+            "metadata": {}
+        }))
+        .unwrap();
+        assert!(reply.found);
+        assert!(reply
+            .data
+            .text_plain()
+            .unwrap()
+            .starts_with("Signature: print"));
+
+        // nothing at the cursor
+        let reply: InspectReply = serde_json::from_value(json!({
+            "status": "ok",
+            "found": false,
+            "data": {},
+            "metadata": {}
+        }))
+        .unwrap();
+        assert!(!reply.found);
+        assert!(reply.data.is_empty());
+        assert_eq!(reply.data.text_plain(), None);
+    }
+
+    #[test]
+    fn input_reply() {
+        let parent: Header = serde_json::from_value(json!({
+            "username": "u", "session": "s", "msg_type": "input_request",
+            "version": "5.3", "msg_id": "input", "date": "d"
+        }))
+        .unwrap();
+        let reply = KernelRequest::reply_to(
+            MessageType::InputReply,
+            Channel::Stdin,
+            &parent,
+            InputReplyContent {
+                value: "42".to_string(),
+            },
+        );
+        let reply = serde_json::to_value(&reply).unwrap();
+        assert_eq!(reply["channel"], "stdin");
+        assert_eq!(reply["header"]["msg_type"], "input_reply");
+        assert_eq!(reply["parent_header"]["msg_id"], "input");
+        assert_eq!(reply["content"]["value"], "42");
+    }
+}
@@ -0,0 +1,342 @@
+use super::comm::{Comm, CommEvent, CommMessage, CommSender};
+use super::connection::KernelConnection;
+use super::Result;
+use bytes::Bytes;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures_util::StreamExt;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Comm target of the widget models.
+pub const WIDGET_TARGET: &str = "jupyter.widget";
+
+/// Keys and indexes to a binary value in the state of a widget, e.g. `["data", 0]`.
+pub type BufferPath = Vec<Value>;
+
+/// Widget models opened by the kernel over a connection, keyed by the model id (the `comm_id`).
+///
+/// The state of each model follows the `update`s from the kernel until the kernel closes it.
+/// The models do not keep the connection open: they are closed when the last [`KernelConnection`] is dropped.
+#[derive(Clone, Default)]
+pub struct WidgetManager {
+    models: Arc<Mutex<HashMap<String, WidgetModel>>>,
+}
+
+impl WidgetManager {
+    /// Track the widgets that the kernel creates over the connection from now on.
+    pub fn new(connection: &KernelConnection) -> Self {
+        let manager = Self::default();
+        let handler_manager = manager.clone();
+        connection.register_comm_target(WIDGET_TARGET, move |comm, open| {
+            handler_manager.open(comm, open)
+        });
+        manager
+    }
+
+    pub fn get(&self, model_id: &str) -> Option<WidgetModel> {
+        self.models.lock().unwrap().get(model_id).cloned()
+    }
+
+    pub fn models(&self) -> Vec<WidgetModel> {
+        self.models.lock().unwrap().values().cloned().collect()
+    }
+
+    fn open(&self, mut comm: Comm, open: CommMessage) {
+        let model = WidgetModel::new(comm.sender());
+        model.apply(&open);
+        self.models
+            .lock()
+            .unwrap()
+            .insert(model.id().to_string(), model.clone());
+
+        let models = self.models.clone();
+        tokio::spawn(async move {
+            while let Some(event) = comm.next().await {
+                match event {
+                    CommEvent::Msg(msg) => model.apply(&msg),
+                    CommEvent::Close(_) => break,
+                }
+            }
+            model.inner.state.lock().unwrap().closed = true;
+            models.lock().unwrap().remove(model.id());
+        });
+    }
+}
+
+/// State of a widget model shared with the kernel.
+#[derive(Clone)]
+pub struct WidgetModel {
+    inner: Arc<WidgetModelInner>,
+}
+
+struct WidgetModelInner {
+    sender: CommSender,
+    state: Mutex<WidgetState>,
+}
+
+#[derive(Default)]
+struct WidgetState {
+    values: Map<String, Value>,
+    buffers: Vec<(BufferPath, Bytes)>,
+    closed: bool,
+    subscribers: Vec<UnboundedSender<Map<String, Value>>>,
+}
+
+impl WidgetState {
+    /// Merge the changes and the buffers.
+    ///
+    /// The binary values are not in `changes` (ipywidgets removes them from the state),
+    /// so the old buffers at or under the paths of `buffers`, or under the changed keys, are replaced.
+    fn merge(&mut self, changes: Map<String, Value>, buffers: Vec<(BufferPath, Bytes)>) {
+        self.buffers.retain(|(path, _)| {
+            let changed =
+                matches!(path.first(), Some(Value::String(key)) if changes.contains_key(key));
+            let replaced = buffers
+                .iter()
+                .any(|(new_path, _)| path.starts_with(new_path));
+            !changed && !replaced
+        });
+        self.buffers.extend(buffers);
+        self.values.extend(changes);
+    }
+}
+
+impl WidgetModel {
+    fn new(sender: CommSender) -> Self {
+        Self {
+            inner: Arc::new(WidgetModelInner {
+                sender,
+                state: Mutex::new(WidgetState::default()),
+            }),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.inner.sender.comm_id()
+    }
+
+    /// e.g. `IntSliderModel`
+    pub fn model_name(&self) -> Option<String> {
+        self.get("_model_name")
+            .and_then(|v| v.as_str().map(|v| v.to_string()))
+    }
+
+    pub fn state(&self) -> Map<String, Value> {
+        self.inner.state.lock().unwrap().values.clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.inner.state.lock().unwrap().values.get(key).cloned()
+    }
+
+    /// The binary value at the path in the state, e.g. the bytes of an `Image`.
+    pub fn buffer(&self, path: &[Value]) -> Option<Bytes> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .buffers
+            .iter()
+            .find(|(buffer_path, _)| buffer_path.as_slice() == path)
+            .map(|(_, buffer)| buffer.clone())
+    }
+
+    pub fn buffers(&self) -> Vec<(BufferPath, Bytes)> {
+        self.inner.state.lock().unwrap().buffers.clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.lock().unwrap().closed
+    }
+
+    /// Set a value and send it to the kernel.
+    pub fn set(&self, key: &str, value: Value) -> Result<()> {
+        let mut state = Map::new();
+        state.insert(key.to_string(), value);
+        self.update(state)
+    }
+
+    /// Merge the state and send it to the kernel.
+    pub fn update(&self, state: Map<String, Value>) -> Result<()> {
+        self.update_with_buffers(state, vec![])
+    }
+
+    /// Merge the state and the binary values at their paths, and send them to the kernel.
+    pub fn update_with_buffers(
+        &self,
+        state: Map<String, Value>,
+        buffers: Vec<(BufferPath, Bytes)>,
+    ) -> Result<()> {
+        let (buffer_paths, binaries): (Vec<_>, Vec<_>) = buffers.iter().cloned().unzip();
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .merge(state.clone(), buffers);
+        self.inner.sender.send_with_buffers(
+            &json!({
+                "method": "update",
+                "state": state,
+                "buffer_paths": buffer_paths,
+            }),
+            &binaries,
+        )
+    }
+
+    /// Ask the kernel to send the full state.
+    pub fn request_state(&self) -> Result<()> {
+        self.inner
+            .sender
+            .send(&json!({ "method": "request_state" }))
+    }
+
+    /// The state changes sent by the kernel.
+    pub fn subscribe(&self) -> UnboundedReceiver<Map<String, Value>> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner.state.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    /// Merge the state of a `comm_open` or an `update` from the kernel.
+    fn apply(&self, msg: &CommMessage) {
+        let method = msg.data.get("method").and_then(|v| v.as_str());
+        if !matches!(method, None | Some("update") | Some("echo_update")) {
+            log::debug!("skip the widget message {method:?}");
+            return;
+        }
+        let changes = match msg.data.get("state").and_then(|v| v.as_object()) {
+            Some(changes) => changes.clone(),
+            None => return,
+        };
+
+        let mut state = self.inner.state.lock().unwrap();
+        state.merge(changes.clone(), buffers_with_paths(&msg.data, &msg.buffers));
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.unbounded_send(changes.clone()).is_ok());
+    }
+}
+
+/// The binary buffers of the message paired with their `buffer_paths`.
+fn buffers_with_paths(data: &Value, buffers: &[Bytes]) -> Vec<(BufferPath, Bytes)> {
+    let buffer_paths = data
+        .get("buffer_paths")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    buffer_paths
+        .into_iter()
+        .zip(buffers)
+        .filter_map(|(path, buffer)| match path {
+            Value::Array(path) => Some((path, buffer.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::connection::test::fake_kernel;
+    use super::*;
+    use crate::client::error::JupyterApiError;
+    use futures::sink::SinkExt;
+    use tokio::time::{sleep, timeout, Duration};
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[test]
+    fn update_state_with_buffers() {
+        // binary values are removed from the dicts and replaced by null in the lists
+        let data = json!({
+            "method": "update",
+            "state": {"value": 3, "data": {"ys": [null, 1]}},
+            "buffer_paths": [["data", "x"], ["data", "ys", 0]],
+        });
+        let buffers = vec![Bytes::from_static(b"x"), Bytes::from_static(b"y")];
+        let buffers = buffers_with_paths(&data, &buffers);
+        assert_eq!(
+            buffers[1],
+            (
+                vec![json!("data"), json!("ys"), json!(0)],
+                Bytes::from_static(b"y")
+            )
+        );
+
+        let mut state = WidgetState::default();
+        state.merge(data["state"].as_object().unwrap().clone(), buffers);
+        assert_eq!(state.values["value"], 3);
+        assert_eq!(state.buffers.len(), 2);
+
+        let mut changes = Map::new();
+        changes.insert("data".to_string(), json!({"ys": [null]}));
+        let y = (
+            vec![json!("data"), json!("ys"), json!(0)],
+            Bytes::from_static(b"y2"),
+        );
+        state.merge(changes, vec![y.clone()]);
+        assert_eq!(state.buffers, vec![y]);
+
+        // e.g. `Image.value`: the key is only in `buffer_paths`
+        let image = |bytes: &'static [u8]| {
+            let data = json!({"method": "update", "state": {}, "buffer_paths": [["value"]]});
+            buffers_with_paths(&data, &[Bytes::from_static(bytes)])
+        };
+        let mut state = WidgetState::default();
+        state.merge(Map::new(), image(b"png1"));
+        state.merge(Map::new(), image(b"png2"));
+        assert_eq!(state.buffers, image(b"png2"));
+
+        assert!(buffers_with_paths(&json!({"method": "custom"}), &[Bytes::new()]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn models_do_not_keep_the_connection_open() {
+        let (connection, mut kernel) = fake_kernel(Default::default()).await;
+        let manager = WidgetManager::new(&connection);
+        let open = json!({
+            "header": {"username": "u", "session": "kernel", "msg_type": "comm_open", "version": "5.3", "msg_id": "m", "date": "d"},
+            "msg_id": "m",
+            "msg_type": "comm_open",
+            "parent_header": {},
+            "metadata": {},
+            "content": {
+                "comm_id": "model",
+                "target_name": WIDGET_TARGET,
+                "data": {"state": {"_model_name": "IntSliderModel"}, "buffer_paths": []},
+            },
+            "buffers": [],
+            "channel": "iopub",
+        });
+        kernel.send(Message::Text(open.to_string())).await.unwrap();
+        let model = timeout(Duration::from_secs(5), async {
+            loop {
+                match manager.get("model") {
+                    Some(model) => break model,
+                    None => sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(model.model_name().as_deref(), Some("IntSliderModel"));
+
+        // the websocket is closed while the manager and the model are still held
+        drop(connection);
+        let closed = timeout(Duration::from_secs(5), kernel.next())
+            .await
+            .unwrap();
+        assert!(matches!(closed, Some(Ok(Message::Close(_))) | None));
+        timeout(Duration::from_secs(5), async {
+            while !model.is_closed() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(manager.models().is_empty());
+        assert!(matches!(
+            model.set("value", json!(1)),
+            Err(JupyterApiError::KernelConnectionClosed)
+        ));
+    }
+}
@@ -1,16 +1,27 @@
+pub mod builder;
+pub mod config;
 pub mod error;
 
 pub mod kernel;
+pub mod proxy;
+pub mod retry;
+pub mod tls;
+pub mod transport;
 pub mod types;
 
+use builder::*;
+use config::*;
 use error::*;
 
 use kernel::*;
-use reqwest::{header, Client};
+use reqwest::{header, Client, Method, Request, RequestBuilder};
+use retry::*;
+use std::time::Duration;
+use transport::*;
 use types::*;
-use url::Url;
 
 type Result<T> = std::result::Result<T, JupyterApiError>;
+#[derive(Debug, Clone, PartialEq)]
 pub enum Credential {
     Token(String),
 }
@@ -32,6 +43,11 @@ pub struct JupyterClient {
     base_url: String,
     credential: Option<Credential>,
     req_client: Client,
+    transport: Transport,
+    /// Applied to the REST api calls over a unix socket, which reqwest does not send.
+    unix_config: UnixRequestConfig,
+    retry_policy: RetryPolicy,
+    kernel_config: KernelClientConfig,
 }
 
 impl Default for JupyterClient {
@@ -40,6 +56,10 @@ impl Default for JupyterClient {
             base_url: "http://localhost:8888".to_string(),
             credential: None,
             req_client: Client::new(),
+            transport: Transport::Tcp,
+            unix_config: UnixRequestConfig::default(),
+            retry_policy: RetryPolicy::default(),
+            kernel_config: KernelClientConfig::default(),
         }
     }
 }
@@ -50,23 +70,31 @@ impl JupyterClient {
         credential: Option<Credential>,
         req_client: Option<Client>,
     ) -> Result<Self> {
-        let parsed_url = Url::parse(base_url)?;
-        if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
-            return Err(JupyterApiError::InvalidJupyterBaseUrlError(
-                base_url.to_string(),
-            ));
-        }
-        let base_url = if base_url.ends_with("/") {
-            base_url[..base_url.len() - 1].to_string()
-        } else {
-            base_url.to_string()
-        };
+        Self::builder(base_url)
+            .credential(credential)
+            .req_client(req_client)
+            .build()
+    }
+
+    pub fn builder(base_url: &str) -> JupyterClientBuilder {
+        JupyterClientBuilder::new(base_url)
+    }
 
-        Ok(Self {
-            base_url,
-            credential,
-            req_client: req_client.unwrap_or_default(),
-        })
+    /// Build a client from `JUPYTER_SERVER_URL`, `JUPYTER_TOKEN`, `JUPYTER_TOKEN_FILE` and `JUPYTER_PROFILE`.
+    /// see [`ServerProfile::from_env`]
+    pub fn from_env() -> Result<Self> {
+        Self::from_server_profile(&ServerProfile::from_env()?)
+    }
+
+    /// Build a client from the named profile in the config file.
+    /// The default profile is used if `name` is `None`. see [`ProfilesConfig`]
+    pub fn from_profile(name: Option<&str>) -> Result<Self> {
+        let config = ProfilesConfig::load()?;
+        Self::from_server_profile(config.profile(name)?)
+    }
+
+    pub fn from_server_profile(profile: &ServerProfile) -> Result<Self> {
+        profile.builder()?.build()
     }
 
     pub fn new_kernel_client(&self, kernel: &Kernel) -> Result<KernelApiClient> {
@@ -76,7 +104,53 @@ impl JupyterClient {
             (&self.base_url["http://".len()..self.base_url.len()], false)
         };
 
-        Ok(kernel.new_kernel_client(url_without_protocol, secure))
+        Ok(kernel.new_kernel_client_with_config(
+            url_without_protocol,
+            secure,
+            self.kernel_config.clone(),
+        ))
+    }
+
+    /// Send the request, retrying idempotent ones on [`JupyterApiError::is_retryable`] errors.
+    /// Error responses are returned as [`JupyterApiError::ApiError`].
+    async fn send(&self, request_builder: RequestBuilder) -> Result<reqwest::Response> {
+        let mut request = request_builder.build()?;
+        let method = request.method().clone();
+        let endpoint = request.url().path().to_string();
+        let mut retries = 0;
+        loop {
+            let next_request = if retries < self.retry_policy.max_retries && is_idempotent(&method)
+            {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            let result = match self.execute(request).await {
+                Ok(response) => check_status(response, &method, &endpoint).await,
+                Err(e) => Err(e),
+            };
+
+            match (next_request, result) {
+                (Some(next_request), Err(e)) if e.is_retryable() => {
+                    retries += 1;
+                    let delay = self.retry_policy.backoff(retries);
+                    log::debug!("retry {method} {endpoint} in {delay:?} ({retries}): {e}");
+                    tokio::time::sleep(delay).await;
+                    request = next_request;
+                }
+                (_, result) => return result,
+            }
+        }
+    }
+
+    async fn execute(&self, request: Request) -> Result<reqwest::Response> {
+        match &self.transport {
+            Transport::Tcp => Ok(self.req_client.execute(request).await?),
+            Transport::Unix(socket_path) => {
+                send_unix(socket_path, request, &self.unix_config).await
+            }
+        }
     }
 
     /// GET /api/contents
@@ -90,7 +164,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(Some(found.json().await?)),
             None => Ok(None),
@@ -115,7 +189,7 @@ impl JupyterClient {
         if let Some(content_type) = content_type {
             request_builder = request_builder.query(&[("type", content_type.as_str())]);
         }
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(Some(found.json().await?)),
             None => Ok(None),
@@ -134,7 +208,7 @@ impl JupyterClient {
         }
         .json(&content);
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(Some(found.json().await?)),
             None => Ok(None),
@@ -157,7 +231,7 @@ impl JupyterClient {
         }
         .json(&content);
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(Some(found.json().await?)),
             None => Ok(None),
@@ -165,6 +239,9 @@ impl JupyterClient {
     }
 
     /// POST /api/kernels
+    ///
+    /// The kernel may not answer the requests yet when this returns.
+    /// see [`Self::start_kernel_and_wait`] and [`KernelApiClient::wait_ready`]
     pub async fn start_kernel(&self, request: KernelPostRequest) -> Result<Kernel> {
         let request_builder = with_auth_header! {
             self.credential,
@@ -175,11 +252,19 @@ impl JupyterClient {
         }
         .json(&request);
 
-        let resp = convert_error(request_builder.send().await?).await?;
-        match resp {
-            Some(found) => Ok(found.json().await?),
-            None => Err(JupyterApiError::EmptyResponse),
-        }
+        let resp = self.send(request_builder).await?;
+        Ok(resp.json().await?)
+    }
+
+    /// Start a kernel and wait until it answers `kernel_info_request` within `timeout`.
+    pub async fn start_kernel_and_wait(
+        &self,
+        request: KernelPostRequest,
+        timeout: Duration,
+    ) -> Result<Kernel> {
+        let kernel = self.start_kernel(request).await?;
+        self.new_kernel_client(&kernel)?.wait_ready(timeout).await?;
+        Ok(kernel)
     }
 
     /// POST /api/kernels/{kernel_id}/interrupt
@@ -192,7 +277,7 @@ impl JupyterClient {
             ))
         };
 
-        convert_error(request_builder.send().await?).await?;
+        self.send(request_builder).await?;
         Ok(())
     }
 
@@ -206,7 +291,7 @@ impl JupyterClient {
             ))
         };
 
-        convert_error(request_builder.send().await?).await?;
+        self.send(request_builder).await?;
         Ok(())
     }
 
@@ -220,7 +305,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(found.json().await?),
             None => Ok(None),
@@ -237,7 +322,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(found.json().await?),
             None => Ok(vec![]),
@@ -254,7 +339,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(found.json().await?),
             None => Ok(KernelSpecs::default()),
@@ -271,7 +356,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(found.json().await?),
             None => Ok(vec![]),
@@ -288,7 +373,7 @@ impl JupyterClient {
             ))
         };
 
-        let resp = convert_error(request_builder.send().await?).await?;
+        let resp = found(self.send(request_builder).await)?;
         match resp {
             Some(found) => Ok(Some(found.json().await?)),
             None => Ok(None),
@@ -296,33 +381,40 @@ impl JupyterClient {
     }
 }
 
-pub async fn convert_error(response: reqwest::Response) -> Result<Option<reqwest::Response>> {
-    if response.status().is_success() {
-        Ok(Some(response))
-    } else {
-        let status = response.status().into();
-        match status {
-            404 => {
-                log::debug!("keycloak returned 404 error");
-                Ok(None)
-            }
-            400 => {
-                let text = response.text().await?;
-                Err(JupyterApiError::BadRequest(text))
-            }
-            _ => {
-                let text = response.text().await?;
-                Err(JupyterApiError::InternalServerError(format!(
-                    "{status}:{text}"
-                )))
-            }
-        }
+/// Treat 404 as `None`.
+fn found<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(found) => Ok(Some(found)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
+async fn check_status(
+    response: reqwest::Response,
+    method: &Method,
+    endpoint: &str,
+) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let text = response.text().await?;
+    log::debug!("{method} {endpoint} returned {status}: {text}");
+    Err(JupyterApiError::ApiError(Box::new(ApiErrorResponse {
+        status: status.as_u16(),
+        method: method.to_string(),
+        endpoint: endpoint.to_string(),
+        body: serde_json::from_str(&text).ok(),
+        text,
+    })))
+}
+
 #[cfg(all(test, feature = "test_with_jupyter"))]
 mod test {
     use super::*;
+    use serde_json::json;
     use serial_test::serial;
     const TEST_JUPYTER_URL: &str = "http://localhost:9990";
 
@@ -334,6 +426,21 @@ mod test {
         assert_eq!(result.kernelspecs.len(), 2);
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn missing_kernel() {
+        let client = JupyterClient::new(TEST_JUPYTER_URL, None, None).unwrap();
+        let kernel_id = "00000000-0000-0000-0000-000000000000";
+
+        let result = client.get_running_kernel(kernel_id).await.unwrap();
+        assert_eq!(result, None);
+
+        let err = client.delete_kernel(kernel_id).await.unwrap_err();
+        let api_error = err.api_error().unwrap();
+        assert_eq!(api_error.kind(), ApiErrorKind::NotFound);
+        assert_eq!(api_error.endpoint, format!("/api/kernels/{kernel_id}"));
+    }
+
     #[tokio::test]
     #[serial]
     async fn run_cmd() {
@@ -349,17 +456,14 @@ mod test {
 
         let kernels = client.get_running_kernels().await.unwrap();
         let kernel = kernels.iter().find(|each| each.name == "rust").unwrap();
-        let kernsl_cli = client.new_kernel_client(&kernel).unwrap();
+        let kernsl_cli = client.new_kernel_client(kernel).unwrap();
 
         let resp = kernsl_cli.run_code("12 * 32".into(), None).await.unwrap();
 
         if let CompositeKernelResponses::SingleResponse(resp) = resp {
             let contents = resp.as_content().unwrap();
             if let Some(KernelContent::ExecuteResultContent(content)) = contents {
-                let expected = Data {
-                    text_plain: Some("384".to_string()),
-                    image_png: None,
-                };
+                let expected = Data::from_iter([(MIME_TEXT_PLAIN, json!("384"))]);
                 assert_eq!(content.data, expected);
             } else {
                 assert!(false);
@@ -387,7 +491,7 @@ mod test {
 
         let kernels = client.get_running_kernels().await.unwrap();
         let kernel = kernels.iter().find(|each| each.name == "rust").unwrap();
-        let kernsl_cli = client.new_kernel_client(&kernel).unwrap();
+        let kernsl_cli = client.new_kernel_client(kernel).unwrap();
 
         let resp = kernsl_cli.run_code("".into(), None).await.unwrap();
         if let CompositeKernelResponses::SingleResponse(resp) = resp {
@@ -405,4 +509,112 @@ mod test {
         client.interrupt_kernel(&start_kernel.id).await.unwrap();
         client.delete_kernel(&start_kernel.id).await.unwrap();
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn run_cmds_over_connection() {
+        let client = JupyterClient::new(TEST_JUPYTER_URL, None, None).unwrap();
+
+        let start_req = KernelPostRequest {
+            name: "rust".to_string(),
+            path: None,
+        };
+        let start_kernel = client.start_kernel(start_req).await.unwrap();
+        let kernsl_cli = client.new_kernel_client(&start_kernel).unwrap();
+        let connection = kernsl_cli.connect().await.unwrap();
+
+        let (first, second) = futures::join!(
+            connection.run_code("1 + 2".into(), None),
+            connection.run_code("3 + 4".into(), None)
+        );
+        for (resp, expected) in [(first, "3"), (second, "7")] {
+            if let CompositeKernelResponses::SingleResponse(resp) = resp.unwrap() {
+                if let Some(KernelContent::ExecuteResultContent(content)) =
+                    resp.as_content().unwrap()
+                {
+                    assert_eq!(content.data.text_plain(), Some(expected.to_string()));
+                    continue;
+                }
+            }
+            panic!("unexpected response for {expected}");
+        }
+
+        client.delete_kernel(&start_kernel.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn execute_stream() {
+        use futures_util::StreamExt;
+
+        let client = JupyterClient::new(TEST_JUPYTER_URL, None, None).unwrap();
+
+        let start_req = KernelPostRequest {
+            name: "rust".to_string(),
+            path: None,
+        };
+        let start_kernel = client.start_kernel(start_req).await.unwrap();
+        let kernsl_cli = client.new_kernel_client(&start_kernel).unwrap();
+
+        let events: Vec<_> = kernsl_cli
+            .execute_stream(r#"println!("streamed");"#)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let events: Vec<_> = events.into_iter().map(|e| e.unwrap()).collect();
+
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ExecutionEvent::Stream(StreamContent { text, .. }) if text.contains("streamed")
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(ExecutionEvent::Status(_)) | Some(ExecutionEvent::Reply(_))
+        ));
+
+        client.delete_kernel(&start_kernel.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn complete() {
+        let client = JupyterClient::new(TEST_JUPYTER_URL, None, None).unwrap();
+
+        let start_req = KernelPostRequest {
+            name: "rust".to_string(),
+            path: None,
+        };
+        let start_kernel = client.start_kernel(start_req).await.unwrap();
+        let kernsl_cli = client.new_kernel_client(&start_kernel).unwrap();
+
+        let code = "std::mem::dr";
+        let reply = kernsl_cli.complete(code, code.len()).await.unwrap();
+        assert!(reply.matches.iter().any(|each| each.contains("drop")));
+        assert!(reply.cursor_start <= reply.cursor_end);
+
+        client.delete_kernel(&start_kernel.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn kernel_info() {
+        let client = JupyterClient::new(TEST_JUPYTER_URL, None, None).unwrap();
+
+        let start_req = KernelPostRequest {
+            name: "rust".to_string(),
+            path: None,
+        };
+        let start_kernel = client.start_kernel(start_req).await.unwrap();
+        let kernsl_cli = client.new_kernel_client(&start_kernel).unwrap();
+
+        let info = kernsl_cli
+            .wait_ready(std::time::Duration::from_secs(30))
+            .await
+            .unwrap();
+        assert_eq!(info.language_info.name, "Rust");
+        assert!(info.protocol_version.starts_with('5'));
+
+        client.delete_kernel(&start_kernel.id).await.unwrap();
+    }
 }
@@ -0,0 +1,135 @@
+use rand::Rng;
+use reqwest::Method;
+use std::time::Duration;
+
+const DEFAULT_RETRY_DELAY_MILLI_SEC: u64 = 500;
+const DEFAULT_MAX_RETRY_DELAY_SEC: u64 = 30;
+const DEFAULT_BACKOFF_MULTIPLIER: f64 = 2.0;
+
+/// How many times and how long to wait before retrying idempotent REST calls and the kernel websocket connect.
+/// Only the errors of [`super::error::JupyterApiError::is_retryable`] are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub delay: Duration,
+    /// Upper bound of the delay.
+    pub max_delay: Duration,
+    /// The delay is multiplied by this on each retry.
+    pub multiplier: f64,
+    /// Randomize each delay between zero and the computed delay, so that clients don't retry all at once.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter.
+    pub fn new(max_retries: u32, delay: Duration) -> Self {
+        Self {
+            max_retries,
+            delay,
+            max_delay: Duration::from_secs(DEFAULT_MAX_RETRY_DELAY_SEC),
+            multiplier: DEFAULT_BACKOFF_MULTIPLIER,
+            jitter: true,
+        }
+    }
+
+    /// The same delay on each retry.
+    pub fn fixed(max_retries: u32, delay: Duration) -> Self {
+        Self {
+            max_retries,
+            delay,
+            max_delay: delay,
+            multiplier: 1.0,
+            jitter: false,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self::new(0, Duration::from_millis(DEFAULT_RETRY_DELAY_MILLI_SEC))
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Delay before the `retry`th retry. (1 origin)
+    ///
+    /// Clamped to `0..=max_delay`, e.g. a negative or NaN `multiplier` retries without delay.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let delay = if delay > 0.0 {
+            delay.min(self.max_delay.as_secs_f64())
+        } else {
+            0.0
+        };
+        let delay = if self.jitter {
+            rand::thread_rng().gen_range(0.0..=delay)
+        } else {
+            delay
+        };
+        // `as_secs_f64` of a `max_delay` near `Duration::MAX` rounds up out of range
+        Duration::try_from_secs_f64(delay)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(125))
+            .max_delay(Duration::from_millis(750))
+            .jitter(false);
+        assert_eq!(policy.backoff(1), Duration::from_millis(125));
+        assert_eq!(policy.backoff(2), Duration::from_millis(250));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(4), Duration::from_millis(750));
+        assert_eq!(policy.backoff(10), Duration::from_millis(750));
+
+        let policy = policy.jitter(true);
+        for retry in 1..5 {
+            assert!(policy.backoff(retry) <= Duration::from_millis(750));
+        }
+
+        let policy = RetryPolicy::fixed(3, Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(100));
+        // out of range settings do not panic
+        for multiplier in [-2.0, f64::NAN, f64::INFINITY] {
+            let policy = RetryPolicy::new(5, Duration::from_millis(100)).multiplier(multiplier);
+            assert!(policy.backoff(2) <= policy.max_delay);
+        }
+        let policy = RetryPolicy::new(5, Duration::from_millis(100)).multiplier(-2.0);
+        assert_eq!(policy.jitter(false).backoff(2), Duration::ZERO);
+        let policy = RetryPolicy::new(5, Duration::MAX).max_delay(Duration::MAX);
+        policy.backoff(3);
+        assert_eq!(policy.jitter(false).backoff(3), Duration::MAX);
+    }
+}
@@ -0,0 +1,147 @@
+use super::error::JupyterApiError;
+use reqwest::ClientBuilder;
+use std::path::Path;
+use tokio_tungstenite::Connector;
+
+type Result<T> = std::result::Result<T, JupyterApiError>;
+
+/// TLS settings shared by the REST api calls and the kernel websocket(`wss://`).
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// PEM encoded root certificates added to the system ones. e.g. a private CA
+    pub root_certificates: Vec<Vec<u8>>,
+    pub client_identity: Option<ClientIdentity>,
+    /// Accept any server certificate including self-signed ones. Use only for testing.
+    pub accept_invalid_certs: bool,
+}
+
+/// PEM encoded client certificate and its PKCS#8 private key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity {
+    pub cert_pem: Vec<u8>,
+    pub key_pem: Vec<u8>,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_root_certificate_pem(mut self, pem: Vec<u8>) -> Self {
+        self.root_certificates.push(pem);
+        self
+    }
+
+    pub fn add_root_certificate_file(self, path: &Path) -> Result<Self> {
+        let pem = std::fs::read(path)?;
+        Ok(self.add_root_certificate_pem(pem))
+    }
+
+    pub fn client_identity_pem(mut self, cert_pem: Vec<u8>, key_pem: Vec<u8>) -> Self {
+        self.client_identity = Some(ClientIdentity { cert_pem, key_pem });
+        self
+    }
+
+    pub fn client_identity_files(self, cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let cert_pem = std::fs::read(cert_path)?;
+        let key_pem = std::fs::read(key_path)?;
+        Ok(self.client_identity_pem(cert_pem, key_pem))
+    }
+
+    pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    pub(crate) fn apply_to_req_client_builder(
+        &self,
+        mut builder: ClientBuilder,
+    ) -> Result<ClientBuilder> {
+        for pem in self.root_certificates.iter() {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+        }
+
+        if let Some(identity) = &self.client_identity {
+            let mut pem = identity.cert_pem.clone();
+            pem.push(b'\n');
+            pem.extend_from_slice(&identity.key_pem);
+            builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+        }
+
+        if self.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder)
+    }
+
+    /// `None` means the default connector of tokio-tungstenite.
+    pub(crate) fn ws_connector(&self) -> Result<Option<Connector>> {
+        if *self == Self::default() {
+            return Ok(None);
+        }
+
+        let mut builder = native_tls::TlsConnector::builder();
+        for pem in self.root_certificates.iter() {
+            builder.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        }
+
+        if let Some(identity) = &self.client_identity {
+            builder.identity(native_tls::Identity::from_pkcs8(
+                &identity.cert_pem,
+                &identity.key_pem,
+            )?);
+        }
+
+        if self.accept_invalid_certs {
+            builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(Some(Connector::NativeTls(builder.build()?)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ws_connector() {
+        assert!(TlsConfig::default().ws_connector().unwrap().is_none());
+        assert!(TlsConfig::new()
+            .accept_invalid_certs(true)
+            .ws_connector()
+            .unwrap()
+            .is_some());
+
+        let invalid_root = TlsConfig::new().add_root_certificate_pem(b"not a pem".to_vec());
+        assert!(matches!(
+            invalid_root.ws_connector(),
+            Err(JupyterApiError::TlsError(_))
+        ));
+        let invalid_identity =
+            TlsConfig::new().client_identity_pem(b"not a cert".to_vec(), b"not a key".to_vec());
+        assert!(matches!(
+            invalid_identity.ws_connector(),
+            Err(JupyterApiError::TlsError(_))
+        ));
+    }
+
+    #[test]
+    fn load_files() {
+        let missing = Path::new("/nonexistent/jupyter-client-rs.pem");
+        assert!(matches!(
+            TlsConfig::new().add_root_certificate_file(missing),
+            Err(JupyterApiError::IoError(_))
+        ));
+        assert!(matches!(
+            TlsConfig::new().client_identity_files(missing, missing),
+            Err(JupyterApiError::IoError(_))
+        ));
+
+        let path = std::env::temp_dir().join(format!("ca-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"pem").unwrap();
+        let tls = TlsConfig::new().add_root_certificate_file(&path).unwrap();
+        assert_eq!(tls.root_certificates, vec![b"pem".to_vec()]);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,240 @@
+use super::error::JupyterApiError;
+use hyper::{Body, Request};
+use percent_encoding::percent_decode_str;
+use reqwest::header::HeaderMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::{TcpStream, UnixStream};
+use url::{Host, Url};
+
+type Result<T> = std::result::Result<T, JupyterApiError>;
+
+pub const UNIX_SOCKET_SCHEME: &str = "http+unix";
+
+/// Host in the request urls sent over a unix domain socket.
+const UNIX_SOCKET_HOST: &str = "localhost";
+
+/// How to reach the jupyter server.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    /// Jupyter server started with `--sock`
+    Unix(PathBuf),
+}
+
+impl Transport {
+    /// Split the base url into the http url used to build requests and the transport.
+    ///
+    /// `http+unix://%2Frun%2Fuser%2F1000%2Fjupyter.sock/base` is the socket `/run/user/1000/jupyter.sock`
+    /// with the base path `/base`.
+    pub(crate) fn from_base_url(base_url: &str) -> Result<(String, Self)> {
+        let parsed_url = Url::parse(base_url)?;
+        match parsed_url.scheme() {
+            "http" | "https" => Ok((base_url.to_string(), Self::Tcp)),
+            UNIX_SOCKET_SCHEME => {
+                let encoded_path = match parsed_url.host_str() {
+                    Some(host) if !host.is_empty() => host,
+                    _ => {
+                        return Err(JupyterApiError::InvalidJupyterBaseUrlError(
+                            base_url.to_string(),
+                        ))
+                    }
+                };
+                let socket_path = percent_decode_str(encoded_path)
+                    .decode_utf8()
+                    .map_err(|_| {
+                        JupyterApiError::InvalidJupyterBaseUrlError(base_url.to_string())
+                    })?;
+
+                let http_url = format!("http://{UNIX_SOCKET_HOST}{}", parsed_url.path());
+                Ok((http_url, Self::Unix(PathBuf::from(socket_path.as_ref()))))
+            }
+            _ => Err(JupyterApiError::InvalidJupyterBaseUrlError(
+                base_url.to_string(),
+            )),
+        }
+    }
+}
+
+/// Settings that reqwest applies in `Client::execute`, applied by hand to the requests over a unix socket.
+#[derive(Default, Debug, Clone)]
+pub(crate) struct UnixRequestConfig {
+    /// The default headers and the user agent.
+    pub headers: HeaderMap,
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+}
+
+/// Send the request over the unix domain socket.
+pub(crate) async fn send_unix(
+    socket_path: &Path,
+    request: reqwest::Request,
+    config: &UnixRequestConfig,
+) -> Result<reqwest::Response> {
+    let mut path_and_query = request.url().path().to_string();
+    if let Some(query) = request.url().query() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+
+    let body = match request.body() {
+        Some(body) => match body.as_bytes() {
+            Some(bytes) => Body::from(bytes.to_vec()),
+            None => {
+                return Err(JupyterApiError::InvalidRequest(
+                    "streaming body is not supported over unix socket".to_string(),
+                ))
+            }
+        },
+        None => Body::empty(),
+    };
+
+    let mut builder = Request::builder()
+        .method(request.method().clone())
+        .uri(path_and_query)
+        .header(hyper::header::HOST, UNIX_SOCKET_HOST);
+    for (name, value) in request.headers() {
+        builder = builder.header(name, value);
+    }
+    for (name, value) in &config.headers {
+        if !request.headers().contains_key(name) {
+            builder = builder.header(name, value);
+        }
+    }
+    let hyper_request = builder.body(body)?;
+
+    let connect = async {
+        let stream = UnixStream::connect(socket_path).await?;
+        Ok(hyper::client::conn::handshake(stream).await?)
+    };
+    let (mut sender, connection) = with_timeout(
+        config.connect_timeout,
+        connect,
+        JupyterApiError::ConnectTimeout,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::debug!("unix socket connection error: {e}");
+        }
+    });
+
+    let send = async {
+        let response = sender.send_request(hyper_request).await?;
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        Ok(hyper::Response::from_parts(parts, body))
+    };
+    let response = with_timeout(
+        config.request_timeout,
+        send,
+        JupyterApiError::RequestTimeout,
+    )
+    .await?;
+    Ok(reqwest::Response::from(response))
+}
+
+/// Open a tcp connection to `host`.
+///
+/// Unlike `Url::host_str`, an IPv6 host is connected to without its brackets.
+pub(crate) async fn connect_tcp(host: Host<&str>, port: u16) -> std::io::Result<TcpStream> {
+    match host {
+        Host::Domain(domain) => TcpStream::connect((domain, port)).await,
+        Host::Ipv4(ip) => TcpStream::connect((ip, port)).await,
+        Host::Ipv6(ip) => TcpStream::connect((ip, port)).await,
+    }
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+    timeout_error: JupyterApiError,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or(Err(timeout_error)),
+        None => future.await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unix_socket_base_url() {
+        let (http_url, transport) =
+            Transport::from_base_url("http+unix://%2Frun%2Fuser%2F1000%2Fjupyter.sock/base/")
+                .unwrap();
+        assert_eq!(http_url, "http://localhost/base/");
+        assert_eq!(
+            transport,
+            Transport::Unix(PathBuf::from("/run/user/1000/jupyter.sock"))
+        );
+
+        let (http_url, transport) = Transport::from_base_url("http://localhost:8888").unwrap();
+        assert_eq!(http_url, "http://localhost:8888");
+        assert_eq!(transport, Transport::Tcp);
+
+        assert!(Transport::from_base_url("ftp://localhost").is_err());
+    }
+
+    #[tokio::test]
+    async fn send_unix_with_config() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let socket_path =
+            std::env::temp_dir().join(format!("jupyter-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let (received_sender, received) = tokio::sync::oneshot::channel();
+        let server = tokio::spawn(async move {
+            // answer the first request, and leave the second one unanswered
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}")
+                .await
+                .unwrap();
+            received_sender
+                .send(String::from_utf8_lossy(&buf[..n]).to_lowercase())
+                .ok();
+            let (_stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("user-agent", "test-agent".parse().unwrap());
+        headers.insert("x-custom", "default".parse().unwrap());
+        let config = UnixRequestConfig {
+            headers,
+            connect_timeout: Some(Duration::from_secs(1)),
+            request_timeout: Some(Duration::from_millis(200)),
+        };
+        let client = reqwest::Client::new();
+        let request = || {
+            client
+                .get("http://localhost/api")
+                .header("x-custom", "request")
+                .build()
+                .unwrap()
+        };
+
+        let response = send_unix(&socket_path, request(), &config).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let received = received.await.unwrap();
+        assert!(received.contains("user-agent: test-agent\r\n"));
+        assert!(received.contains("x-custom: request\r\n"));
+        assert!(!received.contains("x-custom: default"));
+        assert!(matches!(
+            send_unix(&socket_path, request(), &config).await,
+            Err(JupyterApiError::RequestTimeout)
+        ));
+
+        server.abort();
+        std::fs::remove_file(&socket_path).ok();
+    }
+}
@@ -1,4 +1,5 @@
 use super::kernel::*;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -126,12 +127,163 @@ pub struct Output {
     pub output_type: String,
 }
 
+pub const MIME_TEXT_PLAIN: &str = "text/plain";
+pub const MIME_TEXT_HTML: &str = "text/html";
+pub const MIME_TEXT_MARKDOWN: &str = "text/markdown";
+pub const MIME_TEXT_LATEX: &str = "text/latex";
+pub const MIME_IMAGE_PNG: &str = "image/png";
+pub const MIME_IMAGE_JPEG: &str = "image/jpeg";
+pub const MIME_IMAGE_SVG: &str = "image/svg+xml";
+pub const MIME_APPLICATION_JSON: &str = "application/json";
+pub const MIME_APPLICATION_JAVASCRIPT: &str = "application/javascript";
+pub const MIME_PLOTLY: &str = "application/vnd.plotly.v1+json";
+pub const MIME_WIDGET_VIEW: &str = "application/vnd.jupyter.widget-view+json";
+
+/// The richest first, as JupyterLab prefers them.
+pub const DEFAULT_MIME_PREFERENCE: &[&str] = &[
+    MIME_WIDGET_VIEW,
+    MIME_PLOTLY,
+    "application/vnd.vegalite.v5+json",
+    "application/vnd.vegalite.v4+json",
+    "application/vnd.vega.v5+json",
+    MIME_APPLICATION_JAVASCRIPT,
+    MIME_TEXT_HTML,
+    MIME_TEXT_MARKDOWN,
+    MIME_TEXT_LATEX,
+    MIME_IMAGE_SVG,
+    MIME_IMAGE_PNG,
+    MIME_IMAGE_JPEG,
+    MIME_APPLICATION_JSON,
+    MIME_TEXT_PLAIN,
+];
+
+/// MIME bundle of an output, keyed by the MIME type in the order the kernel sent them.
+/// The types without an accessor are kept as raw JSON.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Data {
-    #[serde(rename = "text/plain")]
-    pub text_plain: Option<String>,
-    #[serde(rename = "image/png")]
-    pub image_png: Option<String>,
+#[serde(transparent)]
+pub struct Data(IndexMap<String, Value>);
+
+impl Data {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, mime_type: &str) -> Option<&Value> {
+        self.0.get(mime_type)
+    }
+
+    pub fn insert(&mut self, mime_type: impl Into<String>, value: Value) -> Option<Value> {
+        self.0.insert(mime_type.into(), value)
+    }
+
+    pub fn contains(&self, mime_type: &str) -> bool {
+        self.0.contains_key(mime_type)
+    }
+
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|v| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// A text representation. Notebook files may split it into a list of lines.
+    pub fn text(&self, mime_type: &str) -> Option<String> {
+        match self.get(mime_type)? {
+            Value::String(text) => Some(text.clone()),
+            Value::Array(lines) => lines
+                .iter()
+                .map(|line| line.as_str())
+                .collect::<Option<Vec<_>>>()
+                .map(|lines| lines.concat()),
+            _ => None,
+        }
+    }
+
+    pub fn text_plain(&self) -> Option<String> {
+        self.text(MIME_TEXT_PLAIN)
+    }
+
+    pub fn html(&self) -> Option<String> {
+        self.text(MIME_TEXT_HTML)
+    }
+
+    pub fn markdown(&self) -> Option<String> {
+        self.text(MIME_TEXT_MARKDOWN)
+    }
+
+    pub fn latex(&self) -> Option<String> {
+        self.text(MIME_TEXT_LATEX)
+    }
+
+    pub fn svg(&self) -> Option<String> {
+        self.text(MIME_IMAGE_SVG)
+    }
+
+    pub fn javascript(&self) -> Option<String> {
+        self.text(MIME_APPLICATION_JAVASCRIPT)
+    }
+
+    /// Decoded bytes of the base64 `image/png`.
+    pub fn png(&self) -> Option<Vec<u8>> {
+        self.image(MIME_IMAGE_PNG)
+    }
+
+    /// Decoded bytes of the base64 `image/jpeg`.
+    pub fn jpeg(&self) -> Option<Vec<u8>> {
+        self.image(MIME_IMAGE_JPEG)
+    }
+
+    fn image(&self, mime_type: &str) -> Option<Vec<u8>> {
+        let mut encoded = self.text(mime_type)?;
+        encoded.retain(|c| !c.is_ascii_whitespace());
+        base64::decode(encoded).ok()
+    }
+
+    pub fn json(&self) -> Option<&Value> {
+        self.get(MIME_APPLICATION_JSON)
+    }
+
+    /// Vega or Vega-Lite spec of any version, with its MIME type.
+    pub fn vega(&self) -> Option<(&str, &Value)> {
+        self.iter().find(|(mime_type, _)| {
+            (mime_type.starts_with("application/vnd.vegalite.")
+                || mime_type.starts_with("application/vnd.vega."))
+                && mime_type.ends_with("+json")
+        })
+    }
+
+    pub fn plotly(&self) -> Option<&Value> {
+        self.get(MIME_PLOTLY)
+    }
+
+    /// The richest representation by [`DEFAULT_MIME_PREFERENCE`].
+    pub fn best(&self) -> Option<(&str, &Value)> {
+        self.best_of(DEFAULT_MIME_PREFERENCE)
+    }
+
+    /// The first of the `preference` in the bundle.
+    pub fn best_of(&self, preference: &[&str]) -> Option<(&str, &Value)> {
+        preference
+            .iter()
+            .find_map(|mime_type| self.0.get_key_value(*mime_type))
+            .map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+impl<K: Into<String>> FromIterator<(K, Value)> for Data {
+    fn from_iter<T: IntoIterator<Item = (K, Value)>>(iter: T) -> Self {
+        Self(iter.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -148,19 +300,37 @@ pub struct Kernelspec {
     pub name: String,
 }
 
+/// Language of a notebook or a kernel. Kernels may omit any of the fields.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LanguageInfo {
-    pub codemirror_mode: String,
+    /// Either the mode name or `{"name": .., "version": ..}`, kept as is. see [`Self::codemirror_mode_name`]
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub codemirror_mode: Value,
     pub file_extension: String,
     pub mimetype: String,
     pub name: String,
+    #[serde(rename = "pygments_lexer", alias = "pygment_lexer")]
     pub pygment_lexer: String,
     pub version: String,
 }
 
+impl LanguageInfo {
+    /// Name of the codemirror mode, e.g. `ipython`.
+    pub fn codemirror_mode_name(&self) -> Option<&str> {
+        match &self.codemirror_mode {
+            Value::String(name) => Some(name),
+            mode => mode.get("name").and_then(|v| v.as_str()),
+        }
+    }
+}
+
 /// Type of content
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[derive(
+    Default, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
 pub enum ContentType {
+    #[default]
     #[serde(rename = "directory")]
     Directory,
     #[serde(rename = "file")]
@@ -169,12 +339,6 @@ pub enum ContentType {
     Notebook,
 }
 
-impl Default for ContentType {
-    fn default() -> ContentType {
-        Self::Directory
-    }
-}
-
 impl ContentType {
     pub fn as_str(&self) -> &str {
         match self {
@@ -206,13 +370,22 @@ pub struct Kernel {
 }
 
 impl Kernel {
-    pub(crate) fn new_kernel_client(
+    pub fn new_kernel_client(&self, url_wihtout_protocol: &str, secure: bool) -> KernelApiClient {
+        self.new_kernel_client_with_config(
+            url_wihtout_protocol,
+            secure,
+            KernelClientConfig::default(),
+        )
+    }
+
+    pub fn new_kernel_client_with_config(
         &self,
         url_wihtout_protocol: &str,
         secure: bool,
+        config: KernelClientConfig,
     ) -> KernelApiClient {
-        debug_assert!(!url_wihtout_protocol.ends_with("/"));
-        KernelApiClient::new(url_wihtout_protocol, self.id.as_ref(), secure)
+        debug_assert!(!url_wihtout_protocol.ends_with('/'));
+        KernelApiClient::new_with_config(url_wihtout_protocol, self.id.as_ref(), secure, config)
     }
 }
 
@@ -262,3 +435,67 @@ pub enum FileType {
     File,
     Directory,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn mime_bundle() {
+        let data: Data = serde_json::from_str(
+            r#"{
+                "text/plain": "<Figure>",
+                "text/html": ["<table>\n", "</table>"],
+                "image/png": "iVBO\nRw==\n",
+                "application/vnd.vegalite.v4+json": {"mark": "bar"},
+                "application/x-custom": {"any": 1}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            data.mime_types().collect::<Vec<_>>(),
+            vec![
+                "text/plain",
+                "text/html",
+                "image/png",
+                "application/vnd.vegalite.v4+json",
+                "application/x-custom",
+            ]
+        );
+        assert_eq!(data.text_plain().as_deref(), Some("<Figure>"));
+        assert_eq!(data.html().as_deref(), Some("<table>\n</table>"));
+        assert_eq!(data.png(), Some(vec![0x89, 0x50, 0x4e, 0x47]));
+        assert_eq!(data.svg(), None);
+        assert_eq!(data.vega().unwrap().1, &json!({"mark": "bar"}));
+        assert_eq!(data.get("application/x-custom"), Some(&json!({"any": 1})));
+
+        assert_eq!(data.best().unwrap().0, "application/vnd.vegalite.v4+json");
+        assert_eq!(
+            data.best_of(&[MIME_IMAGE_SVG, MIME_TEXT_PLAIN]).unwrap().0,
+            MIME_TEXT_PLAIN
+        );
+        let json = serde_json::to_string(&data).unwrap();
+        assert!(json.starts_with(r#"{"text/plain":"<Figure>","text/html":"#));
+    }
+
+    #[test]
+    fn language_info_round_trip() {
+        let language_info = json!({
+            "codemirror_mode": {"name": "ipython", "version": 3},
+            "file_extension": ".py",
+            "mimetype": "text/x-python",
+            "name": "python",
+            "pygments_lexer": "ipython3",
+            "version": "3.10.4"
+        });
+        let parsed: LanguageInfo = serde_json::from_value(language_info.clone()).unwrap();
+        assert_eq!(parsed.codemirror_mode_name(), Some("ipython"));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), language_info);
+
+        let parsed: LanguageInfo =
+            serde_json::from_value(json!({"codemirror_mode": "rust"})).unwrap();
+        assert_eq!(parsed.codemirror_mode_name(), Some("rust"));
+    }
+}
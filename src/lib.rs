@@ -1,5 +1,12 @@
 pub mod client;
+pub use client::builder::*;
+pub use client::config::*;
 pub use client::error::*;
 pub use client::kernel::*;
+pub use client::proxy::*;
+pub use client::retry::*;
+pub use client::tls::*;
+pub use client::transport::*;
+pub use client::types::Metadata;
 pub use client::types::*;
 pub use client::*;